@@ -1,16 +1,22 @@
 use lexer;
+use lexer::Number;
 use mopa;
 use parser;
 use repl;
 use parser::*;
 use std::any::Any;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::fs;
 use std::fs::File;
-use std::io::Read;
+use std::io;
+use std::io::{Read, Write};
 use std::path::Path;
 use std::rc::Rc;
+use std::str;
+use std::time::UNIX_EPOCH;
 
 #[macro_export]
 macro_rules! try_or_err_to_string {
@@ -105,17 +111,29 @@ macro_rules! parse_custom_arg {
 
 #[derive(Clone)]
 pub struct Interpreter {
-    root: Rc<RefCell<Environment>>
+    root: Rc<RefCell<Environment>>,
+    fold_case: bool
 }
 
 impl Interpreter {
     pub fn new() -> Interpreter {
-        Interpreter { root: Environment::new_root() }
+        Interpreter { root: Environment::new_root(StandardPorts::new()), fold_case: false }
+    }
+
+    // Reads identifiers case-insensitively, as if every input started with #!fold-case
+    pub fn set_fold_case(&mut self, fold_case: bool) {
+        self.fold_case = fold_case;
     }
 
     pub fn start_repl(&self) {
         println!("\nWelcome to the RustyScheme REPL!");
         repl::start("> ", (|s| {
+            // ,expand <expr> prints the full expansion of an expression instead of evaluating it
+            if s.starts_with(",expand ") {
+                let expanded = try!(self.expand(&s[",expand ".len()..]));
+                let strs: Vec<String> = expanded.iter().map(|v| format!("{:?}", v)).collect();
+                return Ok(strs.join("\n"));
+            }
             match self.execute(&s) {
                 Ok(v) => Ok(format!("{:?}", v)),
                 Err(e) => Err(e)
@@ -124,13 +142,19 @@ impl Interpreter {
     }
 
     pub fn execute(&self, input: &str) -> Result<Value, String> {
-        let parsed = try!(Interpreter::parse(input));
+        let parsed = try!(self.read(input));
         match self.run(&parsed) {
             Ok(v) => Ok(v),
             Err(e) => Err(e.to_string())
         }
     }
 
+    pub fn expand(&self, input: &str) -> Result<Vec<Value>, String> {
+        let parsed = try!(self.read(input));
+        let res: Result<Vec<Value>, RuntimeError> = parsed.iter().map(|v| expand_all(v, self.root.clone())).collect();
+        res.map_err(|e| e.to_string())
+    }
+
     pub fn execute_file(&self, filename: &String) -> Result<Value, String> {
         let path = Path::new(&filename);
         let mut file = File::open(&path).unwrap();
@@ -155,12 +179,7 @@ impl Interpreter {
                         env: Rc<RefCell<Environment>>| ->
                         Result<Value, RuntimeError>
                     {
-                        let mut evaluated_args: Vec<Value> = Vec::new();
-                        for arg in args.iter() {
-                            let v = try!(evaluate_value(arg, env.clone()));
-                            evaluated_args.push(v);
-                        };
-                        func(env.clone(), &evaluated_args)
+                        func(env, args)
                     })))))
     }
 
@@ -171,6 +190,12 @@ impl Interpreter {
         Ok(values)
     }
 
+    fn read(&self, input: &str) -> Result<Vec<Value>, String> {
+        let tokens = try_or_err_to_string!(lexer::tokenize_with_case_folding(input, self.fold_case));
+        let ast = try_or_err_to_string!(parser::parse(&tokens));
+        Ok(Value::from_nodes(&ast))
+    }
+
     fn run(&self, values: &[Value]) -> Result<Value, RuntimeError> {
         evaluate_values(&values, self.root.clone())
     }
@@ -192,52 +217,137 @@ mopafy!(AnyClone);
 pub enum Value {
     Symbol(String),
     Integer(i64),
+    // an exact fraction in lowest terms, with a denominator above 1
+    Rational(i64, i64),
+    Float(f64),
+    Complex(Number, Number),
     Boolean(bool),
+    Char(char),
     String(String),
     List(Vec<Value>),
+    Vector(Rc<RefCell<Vec<Value>>>),
+    Bytevector(Rc<RefCell<Vec<u8>>>),
     Procedure(Function),
     Macro(Vec<String>, Vec<Value>),
+    RecordType(Rc<RecordType>),
+    Record(Record),
+    Environment(Rc<RefCell<Environment>>),
+    Port(Port),
+    Eof,
     CustomType(Custom)
 }
 
 impl PartialEq for Value {
     fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (&Value::Symbol(ref v1), &Value::Symbol(ref v2)) => v1 == v2,
-            (&Value::Integer(v1), &Value::Integer(v2)) => v1 == v2,
-            (&Value::Boolean(v1), &Value::Boolean(v2)) => v1 == v2,
-            (&Value::String(ref v1), &Value::String(ref v2)) => v1 == v2,
-            (&Value::List(ref v1), &Value::List(ref v2)) => v1 == v2,
-            (&Value::Procedure(_), &Value::Procedure(_)) => unimplemented!(),
-            (&Value::Macro(ref s1, ref v1), &Value::Macro(ref s2, ref v2)) =>
-                s1 == s2 && v1 == v2,
-            (&Value::CustomType(_), &Value::CustomType(_)) => unimplemented!(),
-            _ => false
-        }
+        values_equal(self, other, &mut Vec::new())
+    }
+}
+
+// The vectors being compared further up, so cyclic vectors compare equal instead of recursing forever
+type VisitedVectors = Vec<(*const RefCell<Vec<Value>>, *const RefCell<Vec<Value>>)>;
+
+fn values_equal(a: &Value, b: &Value, visited: &mut VisitedVectors) -> bool {
+    match (a, b) {
+        (&Value::Symbol(ref v1), &Value::Symbol(ref v2)) => v1 == v2,
+        (&Value::Integer(v1), &Value::Integer(v2)) => v1 == v2,
+        (&Value::Rational(n1, d1), &Value::Rational(n2, d2)) => n1 == n2 && d1 == d2,
+        // floats are compared bit for bit, like eqv?, so that equality agrees with hashing
+        (&Value::Float(v1), &Value::Float(v2)) => v1.to_bits() == v2.to_bits(),
+        (&Value::Complex(re1, im1), &Value::Complex(re2, im2)) => same_number(re1, re2) && same_number(im1, im2),
+        (&Value::Boolean(v1), &Value::Boolean(v2)) => v1 == v2,
+        (&Value::Char(c1), &Value::Char(c2)) => c1 == c2,
+        (&Value::Eof, &Value::Eof) => true,
+        (&Value::String(ref v1), &Value::String(ref v2)) => v1 == v2,
+        (&Value::List(ref v1), &Value::List(ref v2)) => {
+            v1.len() == v2.len() && v1.iter().zip(v2.iter()).all(|(x, y)| values_equal(x, y, visited))
+        },
+        (&Value::Vector(ref v1), &Value::Vector(ref v2)) => {
+            let pair = (&**v1 as *const RefCell<Vec<Value>>, &**v2 as *const RefCell<Vec<Value>>);
+            if Rc::ptr_eq(v1, v2) || visited.contains(&pair) {
+                return true;
+            }
+            visited.push(pair);
+            let (v1, v2) = (v1.borrow(), v2.borrow());
+            let equal = v1.len() == v2.len() && v1.iter().zip(v2.iter()).all(|(x, y)| values_equal(x, y, visited));
+            visited.pop();
+            equal
+        },
+        (&Value::Bytevector(ref b1), &Value::Bytevector(ref b2)) => *b1.borrow() == *b2.borrow(),
+        (&Value::Procedure(ref f1), &Value::Procedure(ref f2)) => f1 == f2,
+        (&Value::Macro(ref s1, ref v1), &Value::Macro(ref s2, ref v2)) =>
+            s1 == s2 && v1 == v2,
+        (&Value::RecordType(ref t1), &Value::RecordType(ref t2)) => Rc::ptr_eq(t1, t2),
+        (&Value::Record(ref r1), &Value::Record(ref r2)) => Rc::ptr_eq(&r1.fields, &r2.fields),
+        (&Value::Environment(ref e1), &Value::Environment(ref e2)) => Rc::ptr_eq(e1, e2),
+        (&Value::Port(ref p1), &Value::Port(ref p2)) => p1 == p2,
+        (&Value::CustomType(_), &Value::CustomType(_)) => unimplemented!(),
+        _ => false
     }
 }
 
 impl Eq for Value {}
 
+fn same_number(a: Number, b: Number) -> bool {
+    match (a, b) {
+        (Number::Exact(x), Number::Exact(y)) => x == y,
+        (Number::Rational(n1, d1), Number::Rational(n2, d2)) => n1 == n2 && d1 == d2,
+        (Number::Inexact(x), Number::Inexact(y)) => x.to_bits() == y.to_bits(),
+        _ => false
+    }
+}
+
 use std::hash::{Hash, Hasher};
 impl Hash for Value {
     fn hash<H>(&self, state: &mut H) where H: Hasher {
         match self {
             &Value::Symbol(ref v) => v.hash(state),
             &Value::Integer(ref v) => v.hash(state),
+            &Value::Rational(n, d) => (n, d).hash(state),
+            &Value::Float(ref v) => v.to_bits().hash(state),
+            &Value::Complex(re, im) => {
+                for part in [re, im].iter() {
+                    match *part {
+                        Number::Exact(i) => i.hash(state),
+                        Number::Rational(n, d) => (n, d).hash(state),
+                        Number::Inexact(f) => f.to_bits().hash(state)
+                    }
+                }
+            },
             &Value::Boolean(ref v) => v.hash(state),
+            &Value::Char(ref v) => v.hash(state),
+            &Value::Eof => ().hash(state),
             &Value::String(ref v) => v.hash(state),
             &Value::List(ref v) => v.hash(state),
+            // only the length, since the contents might contain the vector itself
+            &Value::Vector(ref v) => v.borrow().len().hash(state),
+            &Value::Bytevector(ref b) => b.borrow().hash(state),
             &Value::Procedure(_) => unimplemented!(),
             &Value::Macro(ref s, ref v) => {
                 s.hash(state);
                 v.hash(state);
             },
+            &Value::RecordType(ref t) => t.name.hash(state),
+            &Value::Record(ref r) => (&*r.fields as *const RefCell<Vec<Value>>).hash(state),
+            &Value::Environment(ref e) => (&**e as *const RefCell<Environment>).hash(state),
+            &Value::Port(ref p) => p.name().hash(state),
             &Value::CustomType(_) => unimplemented!(),
         }
     }
 }
 
+// The type descriptor created by define-record-type
+pub struct RecordType {
+    pub name: String,
+    pub fields: Vec<String>,
+}
+
+// An instance of a record type; clones share the same (mutable) fields
+#[derive(Clone)]
+pub struct Record {
+    pub rtype: Rc<RecordType>,
+    pub fields: Rc<RefCell<Vec<Value>>>,
+}
+
 pub struct Custom {
     pub object: Box<AnyClone>,
     pub tag: String
@@ -251,28 +361,94 @@ impl Clone for Custom {
 
 pub enum Function {
     Native(ValueOperation),
-    Scheme(Vec<String>, Vec<Value>, Rc<RefCell<Environment>>),
+    // argument names, body, closure environment, and the name it was defined with, if any
+    Scheme(Vec<String>, Vec<Value>, Rc<RefCell<Environment>>, Option<String>),
+    Parameter(Rc<Parameter>),
+}
+
+// A parameter object, as created by make-parameter; its value can be dynamically rebound with parameterize
+pub struct Parameter {
+    value: RefCell<Value>,
+    converter: Option<Function>,
 }
 
+// Primitive procedures receive their arguments already evaluated
 pub type ValueOperation =
     Rc<Box<Fn(&[Value], Rc<RefCell<Environment>>) -> Result<Value, RuntimeError>>>;
 
+// Special forms receive their operands unevaluated, and decide for themselves what to evaluate
+pub type SpecialForm = fn(&[Value], Rc<RefCell<Environment>>) -> Result<Value, RuntimeError>;
+
+// Syntactic keywords live outside of any environment. They can't be redefined globally, but local variables
+// can shadow them
+fn special_form(name: &str) -> Option<SpecialForm> {
+    let form: SpecialForm = match name {
+        "define" => native_define,
+        "define-syntax-rule" => native_define_syntax_rule,
+        "define-record-type" => native_define_record_type,
+        "begin" => native_begin,
+        "let" => native_let,
+        "set!" => native_set,
+        "lambda" => native_lambda,
+        "λ" => native_lambda,
+        "if" => native_if,
+        "parameterize" => native_parameterize,
+        "the-environment" => native_the_environment,
+        "and" => native_and,
+        "or" => native_or,
+        "quote" => native_quote,
+        "quasiquote" => native_quasiquote,
+        _ => return None
+    };
+    Some(form)
+}
+
+// The special form a name refers to in an environment, unless a local variable shadows it
+fn keyword(name: &str, env: &Rc<RefCell<Environment>>) -> Option<SpecialForm> {
+    special_form(name).and_then(|form| if env.borrow().is_bound(name) { None } else { Some(form) })
+}
+
 impl Value {
     pub fn new_custom<T: AnyClone>(t: T, tag: &str) -> Value {
         Value::CustomType(Custom {object: t.any_clone(), tag: String::from(tag)})
     }
 
     fn from_nodes(nodes: &[Node]) -> Vec<Value> {
-        nodes.iter().map(Value::from_node).collect()
+        let mut vectors = HashMap::new();
+        nodes.iter().map(|n| Value::from_node_with(n, &mut vectors)).collect()
     }
 
     fn from_node(node: &Node) -> Value {
+        Value::from_node_with(node, &mut HashMap::new())
+    }
+
+    // Labelled vectors are built once and shared by everything that refers to them, including their own elements
+    fn from_node_with(node: &Node, vectors: &mut HashMap<usize, Rc<RefCell<Vec<Value>>>>) -> Value {
         match *node {
             Node::Identifier(ref val) => Value::Symbol(val.clone()),
             Node::Integer(val) => Value::Integer(val),
+            Node::Rational(n, d) => Value::Rational(n, d),
+            Node::Float(val) => Value::Float(val),
+            Node::Complex(re, im) => Value::Complex(re, im),
             Node::Boolean(val) => Value::Boolean(val),
+            Node::Char(val) => Value::Char(val),
             Node::String(ref val) => Value::String(val.clone()),
-            Node::List(ref nodes) => Value::List(Value::from_nodes(&nodes))
+            Node::List(ref nodes) => Value::List(nodes.iter().map(|n| Value::from_node_with(n, vectors)).collect()),
+            Node::Vector(ref nodes) => {
+                let elements = nodes.iter().map(|n| Value::from_node_with(n, vectors)).collect();
+                Value::Vector(Rc::new(RefCell::new(elements)))
+            },
+            Node::LabelledVector(id, ref nodes) => {
+                // a list copied from a labelled datum can hold the same vector again, which is already built
+                let vec = vectors.entry(id).or_insert_with(|| Rc::new(RefCell::new(Vec::new()))).clone();
+                if vec.borrow().is_empty() {
+                    let elements = nodes.iter().map(|n| Value::from_node_with(n, vectors)).collect();
+                    *vec.borrow_mut() = elements;
+                }
+                Value::Vector(vec)
+            },
+            Node::VectorReference(id) => Value::Vector(vectors.entry(id).or_insert_with(|| Rc::new(RefCell::new(Vec::new()))).clone()),
+            Node::Bytevector(ref bytes) => Value::Bytevector(Rc::new(RefCell::new(bytes.clone())))
         }
     }
     // null == empty list
@@ -281,38 +457,248 @@ impl Value {
 
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            Value::Symbol(ref val) => write!(f, "{}", val),
-            Value::Integer(val)    => write!(f, "{}", val),
-            Value::Boolean(val)    => write!(f, "#{}", if val { "t" } else { "f" }),
-            Value::String(ref val) => write!(f, "{}", val),
+        write!(f, "{}", print_value(self, false, Labels::Cycles))
+    }
+}
+
+// Which shared structure gets marked with datum labels when printing
+#[derive(Clone, Copy, PartialEq)]
+enum Labels {
+    Never,
+    Cycles,
+    Shared
+}
+
+// Vectors and records are the only values with identity, so they're the only ones that can be shared
+fn shared_pointer(value: &Value) -> Option<usize> {
+    match *value {
+        Value::Vector(ref v) => Some(&**v as *const RefCell<Vec<Value>> as usize),
+        Value::Record(ref r) => Some(&*r.fields as *const RefCell<Vec<Value>> as usize),
+        _ => None
+    }
+}
+
+fn find_labels(value: &Value, labels: Labels, seen: &mut HashSet<usize>, active: &mut HashSet<usize>, found: &mut HashSet<usize>) {
+    let ptr = shared_pointer(value);
+    if let Some(p) = ptr {
+        // anything reached again while it's still being visited is part of a cycle
+        if active.contains(&p) || (labels == Labels::Shared && seen.contains(&p)) {
+            found.insert(p);
+            return;
+        }
+        if !seen.insert(p) {
+            return;
+        }
+        active.insert(p);
+    }
+    match *value {
+        Value::List(ref list) => for v in list.iter() { find_labels(v, labels, seen, active, found) },
+        Value::Vector(ref vec) => for v in vec.borrow().iter() { find_labels(v, labels, seen, active, found) },
+        Value::Record(ref r) => for v in r.fields.borrow().iter() { find_labels(v, labels, seen, active, found) },
+        _ => ()
+    }
+    if let Some(p) = ptr {
+        active.remove(&p);
+    }
+}
+
+fn is_cyclic(value: &Value) -> bool {
+    let mut found = HashSet::new();
+    find_labels(value, Labels::Cycles, &mut HashSet::new(), &mut HashSet::new(), &mut found);
+    !found.is_empty()
+}
+
+// Prints a value the way write (or display, when write is false) would
+fn print_value(value: &Value, write: bool, labels: Labels) -> String {
+    let mut printer = Printer { write: write, labels: HashMap::new(), next: 0 };
+    if labels != Labels::Never {
+        let mut found = HashSet::new();
+        find_labels(value, labels, &mut HashSet::new(), &mut HashSet::new(), &mut found);
+        printer.labels = found.into_iter().map(|p| (p, None)).collect();
+    }
+    printer.print(value)
+}
+
+struct Printer {
+    write: bool,
+    // the structures that need a label, along with the label once it's been printed
+    labels: HashMap<usize, Option<usize>>,
+    next: usize
+}
+
+impl Printer {
+    fn print(&mut self, value: &Value) -> String {
+        if let Some(p) = shared_pointer(value) {
+            match self.labels.get(&p).cloned() {
+                Some(Some(n)) => return format!("#{}#", n),
+                Some(None) => {
+                    let n = self.next;
+                    self.next += 1;
+                    self.labels.insert(p, Some(n));
+                    return format!("#{}={}", n, self.print_contents(value));
+                },
+                None => ()
+            }
+        }
+        self.print_contents(value)
+    }
+
+    fn print_contents(&mut self, value: &Value) -> String {
+        match *value {
+            Value::Symbol(ref val) => if self.write { write_symbol(val) } else { val.clone() },
+            Value::Integer(val)    => format!("{}", val),
+            Value::Rational(n, d)  => format!("{}/{}", n, d),
+            Value::Float(val)      => format_float(val),
+            Value::Complex(re, im) => format_complex(re, im),
+            Value::Boolean(val)    => format!("#{}", if val { "t" } else { "f" }),
+            Value::Char(val)       => if self.write { write_char(val) } else { val.to_string() },
+            Value::String(ref val) => if self.write { write_string(val) } else { val.clone() },
             Value::List(ref list)  => {
-                let strs: Vec<String> = list.iter().map(|v| format!("{}", v)).collect();
-                write!(f, "({})", &strs.join(" "))
+                let strs: Vec<String> = list.iter().map(|v| self.print(v)).collect();
+                format!("({})", &strs.join(" "))
+            },
+            Value::Vector(ref vec) => {
+                let strs: Vec<String> = vec.borrow().iter().map(|v| self.print(v)).collect();
+                format!("#({})", &strs.join(" "))
+            },
+            Value::Bytevector(ref bytes) => {
+                let strs: Vec<String> = bytes.borrow().iter().map(|b| b.to_string()).collect();
+                format!("#u8({})", &strs.join(" "))
             },
-            Value::Procedure(_)   => write!(f, "#<procedure>"),
-            Value::Macro(_,_)     => write!(f, "#<macro>"),
-            Value::CustomType(Custom{object: _, tag: ref t}) => write!(f, "#<{}>", t)
+            Value::Procedure(Function::Scheme(_, _, _, Some(ref name))) => format!("#<procedure:{}>", name),
+            Value::Procedure(_)   => format!("#<procedure>"),
+            Value::Macro(_,_)     => format!("#<macro>"),
+            Value::RecordType(ref t) => format!("#<record-type:{}>", t.name),
+            Value::Record(ref r)  => {
+                let strs: Vec<String> = r.fields.borrow().iter().map(|v| format!(" {}", self.print(v))).collect();
+                format!("#<{}{}>", r.rtype.name, &strs.concat())
+            },
+            Value::Environment(_) => format!("#<environment>"),
+            Value::Port(Port::Input(ref p)) => format!("#<input-port:{}>", p.borrow().name),
+            Value::Port(Port::Output(ref p)) => format!("#<output-port:{}>", p.borrow().name),
+            Value::Eof            => format!("#<eof>"),
+            Value::CustomType(Custom{object: _, tag: ref t}) => format!("#<{}>", t)
+        }
+    }
+}
+
+// Floats always show a decimal point or exponent, so they read back as floats
+fn format_float(val: f64) -> String {
+    if val.is_nan() {
+        "+nan.0".to_string()
+    } else if val.is_infinite() {
+        if val > 0.0 { "+inf.0".to_string() } else { "-inf.0".to_string() }
+    } else {
+        format!("{:?}", val)
+    }
+}
+
+fn format_real(n: Number) -> String {
+    match n {
+        Number::Exact(i) => i.to_string(),
+        Number::Rational(num, den) => format!("{}/{}", num, den),
+        Number::Inexact(f) => format_float(f)
+    }
+}
+
+fn format_complex(re: Number, im: Number) -> String {
+    // an exact zero real part is left out, as in +2i, and so is an exact imaginary part of one, as in 1-i
+    let real = if re == Number::Exact(0) { String::new() } else { format_real(re) };
+    let imag = match im {
+        Number::Exact(1) => "+".to_string(),
+        Number::Exact(-1) => "-".to_string(),
+        _ => format_real(im)
+    };
+    let sign = if imag.starts_with('+') || imag.starts_with('-') { "" } else { "+" };
+    format!("{}{}{}i", real, sign, imag)
+}
+
+// Symbols that wouldn't read back as themselves are written between pipes
+fn write_symbol(name: &str) -> String {
+    if !symbol_needs_pipes(name) {
+        return name.to_string();
+    }
+    let mut s = String::from("|");
+    for c in name.chars() {
+        match c {
+            '|' => s.push_str("\\|"),
+            '\\' => s.push_str("\\\\"),
+            '\n' => s.push_str("\\n"),
+            '\t' => s.push_str("\\t"),
+            '\r' => s.push_str("\\r"),
+            _ => s.push(c)
+        }
+    }
+    s.push('|');
+    s
+}
+
+// A symbol has to be written between pipes when reading its bare name wouldn't give back the same identifier
+fn symbol_needs_pipes(name: &str) -> bool {
+    let mut chars = name.chars();
+    let first = match chars.next() {
+        Some(c) => c,
+        None => return true
+    };
+    let second = chars.next();
+    let bad_start = match first {
+        '#' | '0'...'9' => true,
+        '.' => second.map_or(false, |c| c.is_digit(10)),
+        // a lone sign is an identifier, +i and +inf.0 are numbers, and anything else after a sign has to be
+        '+' | '-' => match second {
+            None => false,
+            Some('i') | Some('n') => lexer::is_numeric(name),
+            Some(_) => true
+        },
+        _ => false
+    };
+    bad_start || name.chars().any(|c| c.is_whitespace() || match c {
+        '(' | ')' | '[' | ']' | '{' | '}' | '\"' | ',' | '\'' | '`' | ';' | '|' | '\\' => true,
+        _ => false
+    })
+}
+
+// Characters are written the way they're read, using a name for anything that isn't visible
+fn write_char(val: char) -> String {
+    match lexer::CHAR_NAMES.iter().find(|&&(_, c)| c == val) {
+        Some(&(name, _)) => format!("#\\{}", name),
+        None if val.is_control() => format!("#\\x{:x}", val as u32),
+        None => format!("#\\{}", val)
+    }
+}
+
+// Strings are written with escapes, so they read back the same
+fn write_string(val: &str) -> String {
+    let mut s = String::from("\"");
+    for c in val.chars() {
+        match c {
+            '\"' => s.push_str("\\\""),
+            '\\' => s.push_str("\\\\"),
+            '\n' => s.push_str("\\n"),
+            '\t' => s.push_str("\\t"),
+            '\r' => s.push_str("\\r"),
+            _ => s.push(c)
         }
     }
+    s.push('"');
+    s
 }
 
 impl fmt::Debug for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            Value::String(ref val) => write!(f, "\"{}\"", val),
-            Value::List(ref list)  => {
-                let strs: Vec<String> = list.iter().map(|v| format!("{:?}", v)).collect();
-                write!(f, "({})", &strs.join(" "))
-            },
-            _                      => write!(f, "{}", self)
-        }
+        write!(f, "{}", print_value(self, true, Labels::Cycles))
     }
 }
 
 impl PartialEq for Function {
     fn eq(&self, other: &Function) -> bool {
-        self == other
+        match (self, other) {
+            (&Function::Native(ref f1), &Function::Native(ref f2)) => Rc::ptr_eq(f1, f2),
+            (&Function::Scheme(ref a1, ref b1, ref e1, _), &Function::Scheme(ref a2, ref b2, ref e2, _)) =>
+                a1 == a2 && b1 == b2 && Rc::ptr_eq(e1, e2),
+            (&Function::Parameter(ref p1), &Function::Parameter(ref p2)) => Rc::ptr_eq(p1, p2),
+            _ => false
+        }
     }
 }
 
@@ -320,7 +706,8 @@ impl Clone for Function {
     fn clone(&self) -> Function {
         match *self {
             Function::Native(ref func) => Function::Native(func.clone()),
-            Function::Scheme(ref a, ref b, ref env) => Function::Scheme(a.clone(), b.clone(), env.clone())
+            Function::Scheme(ref a, ref b, ref env, ref name) => Function::Scheme(a.clone(), b.clone(), env.clone(), name.clone()),
+            Function::Parameter(ref param) => Function::Parameter(param.clone())
         }
     }
 }
@@ -347,23 +734,395 @@ macro_rules! runtime_error {
     )
 }
 
+// Ports are shared, so reading or writing through any copy of a port value affects all of them
+#[derive(Clone)]
+pub enum Port {
+    Input(Rc<RefCell<InputPort>>),
+    Output(Rc<RefCell<OutputPort>>),
+}
+
+pub struct InputPort {
+    name: String,
+    // None once the port has been closed
+    source: Option<Box<Read>>,
+    // bytes that have been read from the source, but not consumed yet
+    buffer: Vec<u8>,
+    // whether reading from the source might block waiting on a user
+    interactive: bool,
+    // the line, and the number of characters on that line, consumed so far
+    line: u32,
+    column: u32,
+}
+
+pub struct OutputPort {
+    name: String,
+    sink: Sink,
+}
+
+// Where the text written to an output port ends up
+enum Sink {
+    Stdout,
+    Stderr,
+    Buffer(Vec<u8>),
+    File(File),
+    Closed,
+}
+
+impl Port {
+    fn name(&self) -> String {
+        match *self {
+            Port::Input(ref p) => p.borrow().name.clone(),
+            Port::Output(ref p) => p.borrow().name.clone()
+        }
+    }
+}
+
+impl Port {
+    fn close(&self) {
+        match *self {
+            Port::Input(ref p) => p.borrow_mut().source = None,
+            Port::Output(ref p) => {
+                // the standard ports stay open, since there's no way to reopen them
+                let mut port = p.borrow_mut();
+                match port.sink {
+                    Sink::Stdout | Sink::Stderr => (),
+                    _ => port.sink = Sink::Closed
+                }
+            }
+        }
+    }
+}
+
+impl PartialEq for Port {
+    fn eq(&self, other: &Port) -> bool {
+        match (self, other) {
+            (&Port::Input(ref p1), &Port::Input(ref p2)) => Rc::ptr_eq(p1, p2),
+            (&Port::Output(ref p1), &Port::Output(ref p2)) => Rc::ptr_eq(p1, p2),
+            _ => false
+        }
+    }
+}
+
+impl OutputPort {
+    fn write_str(&mut self, s: &str) -> Result<(), RuntimeError> {
+        self.write_bytes(s.as_bytes())
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), RuntimeError> {
+        let res = match self.sink {
+            Sink::Stdout => {
+                let mut out = io::stdout();
+                out.write_all(bytes).and_then(|_| out.flush())
+            },
+            Sink::Stderr => io::stderr().write_all(bytes),
+            Sink::Buffer(ref mut buf) => buf.write_all(bytes),
+            Sink::File(ref mut file) => file.write_all(bytes),
+            Sink::Closed => runtime_error!("Can't write to a closed port: {}", self.name)
+        };
+        match res {
+            Ok(_) => Ok(()),
+            Err(e) => runtime_error!("Error writing to port {}: {}", self.name, e)
+        }
+    }
+}
+
+impl InputPort {
+    // Read more of the source into the buffer, returning false at the end of the input
+    fn fill(&mut self) -> Result<bool, RuntimeError> {
+        let mut chunk = [0; 4096];
+        let res = match self.source {
+            Some(ref mut source) => source.read(&mut chunk),
+            None => runtime_error!("Can't read from a closed port: {}", self.name)
+        };
+        match res {
+            Ok(n) => {
+                self.buffer.extend_from_slice(&chunk[..n]);
+                Ok(n > 0)
+            },
+            Err(e) => runtime_error!("Error reading from port {}: {}", self.name, e)
+        }
+    }
+
+    // The next character, and how many bytes of the buffer it takes up
+    fn next_char(&mut self) -> Result<Option<(char, usize)>, RuntimeError> {
+        loop {
+            if let Some(&first) = self.buffer.first() {
+                let width = match first {
+                    0x00...0x7f => 1,
+                    0xc0...0xdf => 2,
+                    0xe0...0xef => 3,
+                    0xf0...0xf7 => 4,
+                    _ => runtime_error!("Invalid UTF-8 in port {}", self.name)
+                };
+                if self.buffer.len() >= width {
+                    match str::from_utf8(&self.buffer[..width]) {
+                        Ok(s) => return Ok(s.chars().next().map(|c| (c, width))),
+                        Err(_) => runtime_error!("Invalid UTF-8 in port {}", self.name)
+                    }
+                }
+            }
+            if !try!(self.fill()) {
+                if self.buffer.is_empty() {
+                    return Ok(None);
+                }
+                runtime_error!("Invalid UTF-8 at the end of port {}", self.name);
+            }
+        }
+    }
+
+    fn peek_u8(&mut self) -> Result<Option<u8>, RuntimeError> {
+        if self.buffer.is_empty() {
+            try!(self.fill());
+        }
+        Ok(self.buffer.first().cloned())
+    }
+
+    fn read_u8(&mut self) -> Result<Option<u8>, RuntimeError> {
+        let byte = try!(self.peek_u8());
+        if byte.is_some() {
+            self.buffer.remove(0);
+        }
+        Ok(byte)
+    }
+
+    fn read_bytes(&mut self, k: usize) -> Result<Option<Vec<u8>>, RuntimeError> {
+        while self.buffer.len() < k && try!(self.fill()) {}
+        if self.buffer.is_empty() && k > 0 {
+            return Ok(None);
+        }
+        let n = ::std::cmp::min(k, self.buffer.len());
+        Ok(Some(self.buffer.drain(..n).collect()))
+    }
+
+    fn peek_char(&mut self) -> Result<Option<char>, RuntimeError> {
+        Ok(try!(self.next_char()).map(|(c, _)| c))
+    }
+
+    fn read_char(&mut self) -> Result<Option<char>, RuntimeError> {
+        match try!(self.next_char()) {
+            Some((c, width)) => {
+                self.buffer.drain(..width);
+                if c == '\n' {
+                    self.line += 1;
+                    self.column = 0;
+                } else {
+                    self.column += 1;
+                }
+                Ok(Some(c))
+            },
+            None => Ok(None)
+        }
+    }
+
+    // Read up to the next newline, which is consumed but not returned
+    fn read_line(&mut self) -> Result<Option<String>, RuntimeError> {
+        let mut line = String::new();
+        loop {
+            match try!(self.read_char()) {
+                Some('\n') => break,
+                Some(c) => line.push(c),
+                None if line.is_empty() => return Ok(None),
+                None => break
+            }
+        }
+        if line.ends_with('\r') {
+            line.pop();
+        }
+        Ok(Some(line))
+    }
+
+    fn read_string(&mut self, k: usize) -> Result<Option<String>, RuntimeError> {
+        let mut s = String::new();
+        for _ in 0..k {
+            match try!(self.read_char()) {
+                Some(c) => s.push(c),
+                None => break
+            }
+        }
+        if s.is_empty() && k > 0 { Ok(None) } else { Ok(Some(s)) }
+    }
+
+    fn char_ready(&self) -> Result<bool, RuntimeError> {
+        if self.source.is_none() {
+            runtime_error!("Can't read from a closed port: {}", self.name);
+        }
+        // anything but an interactive source can be read without blocking for long
+        Ok(!self.buffer.is_empty() || !self.interactive)
+    }
+
+    // Read one datum, or None if there's nothing left but whitespace and comments
+    fn read_datum(&mut self) -> Result<Option<Value>, RuntimeError> {
+        // characters are taken one at a time, and the text is only tokenized when a token ends outside
+        // of any list, string or comment, which is usually just once the datum is complete
+        let (line, column) = (self.line, self.column);
+        let mut start = None;
+        let mut scanner = DatumScanner::default();
+        let mut text = String::new();
+        loop {
+            let (char_line, char_column) = (self.line, self.column + 1);
+            let c = try!(self.read_char());
+            let (boundary, at_end) = match c {
+                None => (true, true),
+                Some(c) => {
+                    text.push(c);
+                    let was_in_comment = scanner.in_comment();
+                    scanner.push(c);
+                    let comment = was_in_comment || scanner.in_comment();
+                    if comment && scanner.depth <= 0 {
+                        // comments before the datum don't count as its start
+                        start = None;
+                    } else if start.is_none() && !comment && !c.is_whitespace() {
+                        start = Some((char_line, char_column));
+                    }
+                    match c {
+                        ')' | ']' | '}' => (true, false),
+                        _ => match try!(self.peek_char()) {
+                            None => (true, true),
+                            Some(n) => (n.is_whitespace() || "()[]{}\";'`,".contains(n), false)
+                        }
+                    }
+                }
+            };
+            if !at_end && !(boundary && scanner.at_top_level()) {
+                continue;
+            }
+            match lexer::tokenize(&text) {
+                Ok(tokens) => {
+                    if at_end || parser::datum_complete(&tokens) {
+                        match parser::parse(&tokens) {
+                            Ok(nodes) => return Ok(nodes.first().map(Value::from_node)),
+                            Err(e) => {
+                                let (line, column) = start.unwrap_or((line, column + 1));
+                                runtime_error!("Error reading from port {}: {} (line: {}, column: {})", self.name, e, line, column)
+                            }
+                        }
+                    }
+                },
+                Err(ref e) if e.at_eof() && !at_end => (),
+                Err(e) => runtime_error!("Error reading from port {}: {}", self.name, e.offset(line, column))
+            }
+        }
+    }
+}
+
+// Follows just enough of the syntax to tell when text is outside of any list, string or comment
+#[derive(Default)]
+struct DatumScanner {
+    depth: i64,
+    string: bool,
+    pipe: bool,
+    // the next character is taken literally, after a backslash in a string or #\ for a character
+    escape: bool,
+    line_comment: bool,
+    block_comments: u32,
+    prev: Option<char>,
+}
+
+impl DatumScanner {
+    fn push(&mut self, c: char) {
+        let prev = self.prev.take();
+        if self.escape {
+            self.escape = false;
+        } else if self.string || self.pipe {
+            match c {
+                '\\' => self.escape = true,
+                '"' if self.string => self.string = false,
+                '|' if self.pipe => self.pipe = false,
+                _ => {}
+            }
+        } else if self.line_comment {
+            self.line_comment = c != '\n';
+        } else if self.block_comments > 0 {
+            match (prev, c) {
+                (Some('|'), '#') => self.block_comments -= 1,
+                (Some('#'), '|') => self.block_comments += 1,
+                // keep the character for the next pair unless it's just finished one
+                _ => self.prev = Some(c)
+            }
+        } else {
+            match (prev, c) {
+                (Some('#'), '|') => self.block_comments = 1,
+                (Some('#'), '\\') => self.escape = true,
+                (Some('#'), ';') => {},
+                (_, '"') => self.string = true,
+                (_, '|') => self.pipe = true,
+                (_, ';') => self.line_comment = true,
+                (_, '(') | (_, '[') | (_, '{') => self.depth += 1,
+                (_, ')') | (_, ']') | (_, '}') => self.depth -= 1,
+                _ => self.prev = Some(c)
+            }
+        }
+    }
+
+    fn in_comment(&self) -> bool {
+        self.line_comment || self.block_comments > 0
+    }
+
+    fn at_top_level(&self) -> bool {
+        self.depth <= 0 && !self.string && !self.pipe && !self.escape && !self.in_comment()
+    }
+}
+
+fn new_input_port(name: &str, source: Box<Read>) -> Value {
+    let port = InputPort { name: name.to_string(), source: Some(source), buffer: Vec::new(), interactive: false, line: 1, column: 0 };
+    Value::Port(Port::Input(Rc::new(RefCell::new(port))))
+}
+
+fn new_output_port(name: &str, sink: Sink) -> Value {
+    Value::Port(Port::Output(Rc::new(RefCell::new(OutputPort { name: name.to_string(), sink: sink }))))
+}
+
+// Run f with param temporarily rebound to val, restoring the old value whether or not f succeeds
+fn with_parameter<F>(param: &Rc<Parameter>, val: Value, f: F) -> Result<Value, RuntimeError>
+    where F: FnOnce() -> Result<Value, RuntimeError>
+{
+    let old = param.value.replace(val);
+    let res = f();
+    *param.value.borrow_mut() = old;
+    res
+}
+
+fn new_stdin_port() -> Value {
+    let port = InputPort { name: "stdin".to_string(), source: Some(Box::new(io::stdin())), buffer: Vec::new(), interactive: true, line: 1, column: 0 };
+    Value::Port(Port::Input(Rc::new(RefCell::new(port))))
+}
+
+// The parameters behind current-input-port, current-output-port and current-error-port.  Each interpreter
+// has its own, shared by every environment it creates.
+struct StandardPorts {
+    input: Rc<Parameter>,
+    output: Rc<Parameter>,
+    error: Rc<Parameter>,
+}
+
+impl StandardPorts {
+    fn new() -> Rc<StandardPorts> {
+        Rc::new(StandardPorts {
+            input: Rc::new(Parameter { value: RefCell::new(new_stdin_port()), converter: None }),
+            output: Rc::new(Parameter { value: RefCell::new(new_output_port("stdout", Sink::Stdout)), converter: None }),
+            error: Rc::new(Parameter { value: RefCell::new(new_output_port("stderr", Sink::Stderr)), converter: None }),
+        })
+    }
+
+    fn of(env: &Rc<RefCell<Environment>>) -> Rc<StandardPorts> {
+        env.borrow().ports.clone()
+    }
+}
+
 pub struct Environment {
     parent: Option<Rc<RefCell<Environment>>>,
-    values: HashMap<String, Value>
+    values: HashMap<String, Value>,
+    // the same for the root and all of its descendants
+    ports: Rc<StandardPorts>,
+    // internal definitions that are bound, but haven't been initialized yet
+    unassigned: HashSet<String>
 }
 
 impl Environment {
-    fn new_root() -> Rc<RefCell<Environment>> {
-        let mut env = Environment { parent: None, values: HashMap::new() };
+    fn new_root(ports: Rc<StandardPorts>) -> Rc<RefCell<Environment>> {
+        let mut env = Environment { parent: None, values: HashMap::new(), ports: ports, unassigned: HashSet::new() };
         let predefined_functions = &[
-            ("define", Function::Native(Rc::new(Box::new(native_define)))),
-            ("define-syntax-rule", Function::Native(Rc::new(Box::new(native_define_syntax_rule)))),
-            ("begin", Function::Native(Rc::new(Box::new(native_begin)))),
-            ("let", Function::Native(Rc::new(Box::new(native_let)))),
-            ("set!", Function::Native(Rc::new(Box::new(native_set)))),
-            ("lambda", Function::Native(Rc::new(Box::new(native_lambda)))),
-            ("λ", Function::Native(Rc::new(Box::new(native_lambda)))),
-            ("if", Function::Native(Rc::new(Box::new(native_if)))),
+            ("make-parameter", Function::Native(Rc::new(Box::new(native_make_parameter)))),
             ("+", Function::Native(Rc::new(Box::new(native_plus)))),
             ("-", Function::Native(Rc::new(Box::new(native_minus)))),
             ("*", Function::Native(Rc::new(Box::new(native_multiply)))),
@@ -371,29 +1130,209 @@ impl Environment {
             ("<", Function::Native(Rc::new(Box::new(native_lessthan)))),
             (">", Function::Native(Rc::new(Box::new(native_greaterthan)))),
             ("=", Function::Native(Rc::new(Box::new(native_equal)))),
-            ("and", Function::Native(Rc::new(Box::new(native_and)))),
-            ("or", Function::Native(Rc::new(Box::new(native_or)))),
+            ("<=", Function::Native(Rc::new(Box::new(native_lessthan_or_equal)))),
+            (">=", Function::Native(Rc::new(Box::new(native_greaterthan_or_equal)))),
+            ("quotient", Function::Native(Rc::new(Box::new(native_quotient)))),
+            ("remainder", Function::Native(Rc::new(Box::new(native_remainder)))),
+            ("modulo", Function::Native(Rc::new(Box::new(native_modulo)))),
+            ("floor/", Function::Native(Rc::new(Box::new(native_floor_divide)))),
+            ("truncate/", Function::Native(Rc::new(Box::new(native_truncate_divide)))),
+            ("abs", Function::Native(Rc::new(Box::new(native_abs)))),
+            ("min", Function::Native(Rc::new(Box::new(native_min)))),
+            ("max", Function::Native(Rc::new(Box::new(native_max)))),
+            ("gcd", Function::Native(Rc::new(Box::new(native_gcd)))),
+            ("lcm", Function::Native(Rc::new(Box::new(native_lcm)))),
+            ("expt", Function::Native(Rc::new(Box::new(native_expt)))),
+            ("exact-integer-sqrt", Function::Native(Rc::new(Box::new(native_exact_integer_sqrt)))),
+            ("zero?", Function::Native(Rc::new(Box::new(native_is_zero)))),
+            ("positive?", Function::Native(Rc::new(Box::new(native_is_positive)))),
+            ("negative?", Function::Native(Rc::new(Box::new(native_is_negative)))),
+            ("odd?", Function::Native(Rc::new(Box::new(native_is_odd)))),
+            ("even?", Function::Native(Rc::new(Box::new(native_is_even)))),
+            ("number?", Function::Native(Rc::new(Box::new(native_is_number)))),
+            ("integer?", Function::Native(Rc::new(Box::new(native_is_integer)))),
+            ("rational?", Function::Native(Rc::new(Box::new(native_is_rational)))),
+            ("numerator", Function::Native(Rc::new(Box::new(native_numerator)))),
+            ("denominator", Function::Native(Rc::new(Box::new(native_denominator)))),
+            ("sqrt", Function::Native(Rc::new(Box::new(native_sqrt)))),
+            ("exp", Function::Native(Rc::new(Box::new(native_exp)))),
+            ("log", Function::Native(Rc::new(Box::new(native_log)))),
+            ("sin", Function::Native(Rc::new(Box::new(native_sin)))),
+            ("cos", Function::Native(Rc::new(Box::new(native_cos)))),
+            ("tan", Function::Native(Rc::new(Box::new(native_tan)))),
+            ("asin", Function::Native(Rc::new(Box::new(native_asin)))),
+            ("acos", Function::Native(Rc::new(Box::new(native_acos)))),
+            ("atan", Function::Native(Rc::new(Box::new(native_atan)))),
+            ("floor", Function::Native(Rc::new(Box::new(native_floor)))),
+            ("ceiling", Function::Native(Rc::new(Box::new(native_ceiling)))),
+            ("round", Function::Native(Rc::new(Box::new(native_round)))),
+            ("truncate", Function::Native(Rc::new(Box::new(native_truncate)))),
+            ("exact", Function::Native(Rc::new(Box::new(native_exact)))),
+            ("inexact", Function::Native(Rc::new(Box::new(native_inexact)))),
+            ("square", Function::Native(Rc::new(Box::new(native_square)))),
+            ("complex?", Function::Native(Rc::new(Box::new(native_is_complex)))),
+            ("real?", Function::Native(Rc::new(Box::new(native_is_real)))),
+            ("make-rectangular", Function::Native(Rc::new(Box::new(native_make_rectangular)))),
+            ("make-polar", Function::Native(Rc::new(Box::new(native_make_polar)))),
+            ("real-part", Function::Native(Rc::new(Box::new(native_real_part)))),
+            ("imag-part", Function::Native(Rc::new(Box::new(native_imag_part)))),
+            ("magnitude", Function::Native(Rc::new(Box::new(native_magnitude)))),
+            ("angle", Function::Native(Rc::new(Box::new(native_angle)))),
+            ("bitwise-and", Function::Native(Rc::new(Box::new(native_bitwise_and)))),
+            ("bitwise-or", Function::Native(Rc::new(Box::new(native_bitwise_or)))),
+            ("bitwise-xor", Function::Native(Rc::new(Box::new(native_bitwise_xor)))),
+            ("bitwise-not", Function::Native(Rc::new(Box::new(native_bitwise_not)))),
+            ("arithmetic-shift", Function::Native(Rc::new(Box::new(native_arithmetic_shift)))),
+            ("bit-count", Function::Native(Rc::new(Box::new(native_bit_count)))),
+            ("integer-length", Function::Native(Rc::new(Box::new(native_integer_length)))),
+            ("bit-set?", Function::Native(Rc::new(Box::new(native_is_bit_set)))),
+            ("copy-bit", Function::Native(Rc::new(Box::new(native_copy_bit)))),
+            ("bit-field", Function::Native(Rc::new(Box::new(native_bit_field)))),
             ("null?", Function::Native(Rc::new(Box::new(native_null)))),
             ("list", Function::Native(Rc::new(Box::new(native_list)))),
             ("car", Function::Native(Rc::new(Box::new(native_car)))),
             ("cdr", Function::Native(Rc::new(Box::new(native_cdr)))),
             ("cons", Function::Native(Rc::new(Box::new(native_cons)))),
             ("append", Function::Native(Rc::new(Box::new(native_append)))),
-            ("quote", Function::Native(Rc::new(Box::new(native_quote)))),
-            ("quasiquote", Function::Native(Rc::new(Box::new(native_quasiquote)))),
+            ("length", Function::Native(Rc::new(Box::new(native_length)))),
+            ("reverse", Function::Native(Rc::new(Box::new(native_reverse)))),
+            ("list-ref", Function::Native(Rc::new(Box::new(native_list_ref)))),
+            ("list-tail", Function::Native(Rc::new(Box::new(native_list_tail)))),
+            ("last", Function::Native(Rc::new(Box::new(native_last)))),
+            ("iota", Function::Native(Rc::new(Box::new(native_iota)))),
+            ("map", Function::Native(Rc::new(Box::new(native_map)))),
+            ("for-each", Function::Native(Rc::new(Box::new(native_for_each)))),
+            ("filter", Function::Native(Rc::new(Box::new(native_filter)))),
+            ("remove", Function::Native(Rc::new(Box::new(native_remove)))),
+            ("partition", Function::Native(Rc::new(Box::new(native_partition)))),
+            ("fold", Function::Native(Rc::new(Box::new(native_fold)))),
+            ("fold-right", Function::Native(Rc::new(Box::new(native_fold_right)))),
+            ("reduce", Function::Native(Rc::new(Box::new(native_reduce)))),
+            ("any", Function::Native(Rc::new(Box::new(native_any)))),
+            ("every", Function::Native(Rc::new(Box::new(native_every)))),
+            ("find", Function::Native(Rc::new(Box::new(native_find)))),
+            ("delete", Function::Native(Rc::new(Box::new(native_delete)))),
+            ("delete-duplicates", Function::Native(Rc::new(Box::new(native_delete_duplicates)))),
+            ("vector", Function::Native(Rc::new(Box::new(native_vector)))),
+            ("make-vector", Function::Native(Rc::new(Box::new(native_make_vector)))),
+            ("vector?", Function::Native(Rc::new(Box::new(native_is_vector)))),
+            ("vector-length", Function::Native(Rc::new(Box::new(native_vector_length)))),
+            ("vector-ref", Function::Native(Rc::new(Box::new(native_vector_ref)))),
+            ("vector-set!", Function::Native(Rc::new(Box::new(native_vector_set)))),
+            ("vector->list", Function::Native(Rc::new(Box::new(native_vector_to_list)))),
+            ("list->vector", Function::Native(Rc::new(Box::new(native_list_to_vector)))),
+            ("sort", Function::Native(Rc::new(Box::new(native_sort)))),
+            ("sort!", Function::Native(Rc::new(Box::new(native_sort_in_place)))),
+            ("list-sort", Function::Native(Rc::new(Box::new(native_list_sort)))),
+            ("vector-sort", Function::Native(Rc::new(Box::new(native_vector_sort)))),
+            ("merge", Function::Native(Rc::new(Box::new(native_merge)))),
+            ("vector-binary-search", Function::Native(Rc::new(Box::new(native_vector_binary_search)))),
+            ("eq?", Function::Native(Rc::new(Box::new(native_eq)))),
+            ("eqv?", Function::Native(Rc::new(Box::new(native_eqv)))),
+            ("equal?", Function::Native(Rc::new(Box::new(native_equal_values)))),
+            ("memq", Function::Native(Rc::new(Box::new(native_memq)))),
+            ("memv", Function::Native(Rc::new(Box::new(native_memv)))),
+            ("member", Function::Native(Rc::new(Box::new(native_member)))),
+            ("assq", Function::Native(Rc::new(Box::new(native_assq)))),
+            ("assv", Function::Native(Rc::new(Box::new(native_assv)))),
+            ("assoc", Function::Native(Rc::new(Box::new(native_assoc)))),
             ("error", Function::Native(Rc::new(Box::new(native_error)))),
             ("apply", Function::Native(Rc::new(Box::new(native_apply)))),
             ("eval", Function::Native(Rc::new(Box::new(native_eval)))),
+            ("macroexpand-1", Function::Native(Rc::new(Box::new(native_macroexpand_1)))),
+            ("macroexpand", Function::Native(Rc::new(Box::new(native_macroexpand)))),
+            ("expand", Function::Native(Rc::new(Box::new(native_expand)))),
+            ("scheme-report-environment", Function::Native(Rc::new(Box::new(native_scheme_report_environment)))),
+            ("null-environment", Function::Native(Rc::new(Box::new(native_null_environment)))),
+            ("interaction-environment", Function::Native(Rc::new(Box::new(native_interaction_environment)))),
+            ("environment", Function::Native(Rc::new(Box::new(native_environment)))),
+            ("environment-bound?", Function::Native(Rc::new(Box::new(native_environment_bound)))),
+            ("environment-define!", Function::Native(Rc::new(Box::new(native_environment_define)))),
             ("write", Function::Native(Rc::new(Box::new(native_write)))),
             ("display", Function::Native(Rc::new(Box::new(native_display)))),
             ("displayln", Function::Native(Rc::new(Box::new(native_displayln)))),
             ("print", Function::Native(Rc::new(Box::new(native_print)))),
+            ("write-shared", Function::Native(Rc::new(Box::new(native_write_shared)))),
+            ("write-simple", Function::Native(Rc::new(Box::new(native_write_simple)))),
             ("newline", Function::Native(Rc::new(Box::new(native_newline)))),
+            ("format", Function::Native(Rc::new(Box::new(native_format)))),
+            ("printf", Function::Native(Rc::new(Box::new(native_printf)))),
+            ("fprintf", Function::Native(Rc::new(Box::new(native_fprintf)))),
+            ("port?", Function::Native(Rc::new(Box::new(native_is_port)))),
+            ("input-port?", Function::Native(Rc::new(Box::new(native_is_input_port)))),
+            ("output-port?", Function::Native(Rc::new(Box::new(native_is_output_port)))),
+            ("open-input-string", Function::Native(Rc::new(Box::new(native_open_input_string)))),
+            ("open-output-string", Function::Native(Rc::new(Box::new(native_open_output_string)))),
+            ("get-output-string", Function::Native(Rc::new(Box::new(native_get_output_string)))),
+            ("call-with-output-string", Function::Native(Rc::new(Box::new(native_call_with_output_string)))),
+            ("with-output-to-string", Function::Native(Rc::new(Box::new(native_with_output_to_string)))),
+            ("with-input-from-string", Function::Native(Rc::new(Box::new(native_with_input_from_string)))),
+            ("close-port", Function::Native(Rc::new(Box::new(native_close_port)))),
+            ("char?", Function::Native(Rc::new(Box::new(native_is_char)))),
+            ("read-char", Function::Native(Rc::new(Box::new(native_read_char)))),
+            ("peek-char", Function::Native(Rc::new(Box::new(native_peek_char)))),
+            ("read-line", Function::Native(Rc::new(Box::new(native_read_line)))),
+            ("read-string", Function::Native(Rc::new(Box::new(native_read_string)))),
+            ("char-ready?", Function::Native(Rc::new(Box::new(native_char_ready)))),
+            ("eof-object", Function::Native(Rc::new(Box::new(native_eof_object)))),
+            ("read", Function::Native(Rc::new(Box::new(native_read)))),
+            ("bytevector?", Function::Native(Rc::new(Box::new(native_is_bytevector)))),
+            ("bytevector", Function::Native(Rc::new(Box::new(native_bytevector)))),
+            ("make-bytevector", Function::Native(Rc::new(Box::new(native_make_bytevector)))),
+            ("bytevector-length", Function::Native(Rc::new(Box::new(native_bytevector_length)))),
+            ("bytevector-u8-ref", Function::Native(Rc::new(Box::new(native_bytevector_u8_ref)))),
+            ("bytevector-u8-set!", Function::Native(Rc::new(Box::new(native_bytevector_u8_set)))),
+            ("bytevector-copy", Function::Native(Rc::new(Box::new(native_bytevector_copy)))),
+            ("bytevector-append", Function::Native(Rc::new(Box::new(native_bytevector_append)))),
+            ("utf8->string", Function::Native(Rc::new(Box::new(native_utf8_to_string)))),
+            ("string->utf8", Function::Native(Rc::new(Box::new(native_string_to_utf8)))),
+            ("open-input-bytevector", Function::Native(Rc::new(Box::new(native_open_input_bytevector)))),
+            ("open-output-bytevector", Function::Native(Rc::new(Box::new(native_open_output_bytevector)))),
+            ("get-output-bytevector", Function::Native(Rc::new(Box::new(native_get_output_bytevector)))),
+            ("open-binary-input-file", Function::Native(Rc::new(Box::new(native_open_input_file)))),
+            ("open-binary-output-file", Function::Native(Rc::new(Box::new(native_open_output_file)))),
+            ("read-u8", Function::Native(Rc::new(Box::new(native_read_u8)))),
+            ("peek-u8", Function::Native(Rc::new(Box::new(native_peek_u8)))),
+            ("read-bytevector", Function::Native(Rc::new(Box::new(native_read_bytevector)))),
+            ("write-u8", Function::Native(Rc::new(Box::new(native_write_u8)))),
+            ("write-bytevector", Function::Native(Rc::new(Box::new(native_write_bytevector)))),
+            ("read-from-string", Function::Native(Rc::new(Box::new(native_read_from_string)))),
+            ("string->datum", Function::Native(Rc::new(Box::new(native_read_from_string)))),
+            ("eof-object?", Function::Native(Rc::new(Box::new(native_is_eof_object)))),
+            ("close-input-port", Function::Native(Rc::new(Box::new(native_close_input_port)))),
+            ("close-output-port", Function::Native(Rc::new(Box::new(native_close_output_port)))),
+            ("open-input-file", Function::Native(Rc::new(Box::new(native_open_input_file)))),
+            ("open-output-file", Function::Native(Rc::new(Box::new(native_open_output_file)))),
+            ("call-with-input-file", Function::Native(Rc::new(Box::new(native_call_with_input_file)))),
+            ("call-with-output-file", Function::Native(Rc::new(Box::new(native_call_with_output_file)))),
+            ("with-input-from-file", Function::Native(Rc::new(Box::new(native_with_input_from_file)))),
+            ("with-output-to-file", Function::Native(Rc::new(Box::new(native_with_output_to_file)))),
+            ("file-exists?", Function::Native(Rc::new(Box::new(native_file_exists)))),
+            ("directory-exists?", Function::Native(Rc::new(Box::new(native_directory_exists)))),
+            ("delete-file", Function::Native(Rc::new(Box::new(native_delete_file)))),
+            ("rename-file", Function::Native(Rc::new(Box::new(native_rename_file)))),
+            ("directory-list", Function::Native(Rc::new(Box::new(native_directory_list)))),
+            ("make-directory", Function::Native(Rc::new(Box::new(native_make_directory)))),
+            ("file-size", Function::Native(Rc::new(Box::new(native_file_size)))),
+            ("file-or-directory-modify-seconds", Function::Native(Rc::new(Box::new(native_file_modify_seconds)))),
             ];
         for item in predefined_functions.iter() {
             let (name, ref func) = *item;
             env.define(name.to_string(), Value::Procedure(func.clone())).unwrap();
         }
+        let standard_ports = &[
+            ("current-input-port", env.ports.input.clone()),
+            ("current-output-port", env.ports.output.clone()),
+            ("current-error-port", env.ports.error.clone()),
+            ];
+        for &(name, ref param) in standard_ports.iter() {
+            env.define(name.to_string(), Value::Procedure(Function::Parameter(param.clone()))).unwrap();
+        }
+        Rc::new(RefCell::new(env))
+    }
+
+    fn new_empty(ports: Rc<StandardPorts>) -> Rc<RefCell<Environment>> {
+        let env = Environment { parent: None, values: HashMap::new(), ports: ports, unassigned: HashSet::new() };
         Rc::new(RefCell::new(env))
     }
 
@@ -402,25 +1341,40 @@ impl Environment {
     }
 
     fn new_child(parent: Rc<RefCell<Environment>>) -> Rc<RefCell<Environment>> {
-        let env = Environment { parent: Some(parent), values: HashMap::new() };
+        let ports = parent.borrow().ports.clone();
+        let env = Environment { parent: Some(parent), values: HashMap::new(), ports: ports, unassigned: HashSet::new() };
         Rc::new(RefCell::new(env))
     }
 
     // Define a variable at the current level
     pub fn define(&mut self, key: String, value: Value) -> Result<(), RuntimeError> {
+        // only global definitions could break the language for everything else
+        if self.parent.is_none() && special_form(&key).is_some() {
+            runtime_error!("Can't bind a syntactic keyword: {}", key);
+        }
+        self.unassigned.remove(&key);
         self.values.insert(key, value);
         Ok(())
     }
 
+    // Bind a variable at the current level without initializing it, so that it shadows any outer variables
+    fn declare(&mut self, key: String) {
+        if !self.values.contains_key(&key) {
+            self.unassigned.insert(key);
+        }
+    }
+
     // Set a variable to a value, at any level in the env, or throw a runtime error if it isn't defined at all
     fn set(&mut self, key: String, value: Value) -> Result<(), RuntimeError>  {
-        if self.values.contains_key(&key) {
+        if self.values.contains_key(&key) || self.unassigned.contains(&key) {
+            self.unassigned.remove(&key);
             self.values.insert(key, value);
             Ok(())
         } else {
             // recurse up the environment tree until a value is found or the end is reached
             match self.parent {
                 Some(ref parent) => parent.borrow_mut().set(key, value),
+                None if special_form(&key).is_some() => runtime_error!("Can't set! a syntactic keyword: {}", key),
                 None => runtime_error!("Can't set! an undefined variable: {:?}", key)
             }
         }
@@ -430,6 +1384,9 @@ impl Environment {
         match self.values.get(key) {
             Some(val) => Some(val.clone()),
             None => {
+                if self.unassigned.contains(key) {
+                    return None;
+                }
                 // recurse up the environment tree until a value is found or the end is reached
                 match self.parent {
                     Some(ref parent) => parent.borrow().get(key),
@@ -439,6 +1396,32 @@ impl Environment {
         }
     }
 
+    // Check whether a variable is bound at any level, even if it hasn't been initialized yet
+    fn is_bound(&self, key: &str) -> bool {
+        if self.values.contains_key(key) || self.unassigned.contains(key) {
+            true
+        } else {
+            match self.parent {
+                Some(ref parent) => parent.borrow().is_bound(key),
+                None => false
+            }
+        }
+    }
+
+    // Check whether the closest binding of a variable is one that hasn't been initialized yet
+    fn is_unassigned(&self, key: &String) -> bool {
+        if self.values.contains_key(key) {
+            false
+        } else if self.unassigned.contains(key) {
+            true
+        } else {
+            match self.parent {
+                Some(ref parent) => parent.borrow().is_unassigned(key),
+                None => false
+            }
+        }
+    }
+
     pub fn get_root(env_ref: Rc<RefCell<Environment>>) -> Rc<RefCell<Environment>> {
         let env = env_ref.borrow();
         match env.parent {
@@ -456,26 +1439,114 @@ pub fn evaluate_values(values: &[Value], env: Rc<RefCell<Environment>>) -> Resul
     Ok(res)
 }
 
-pub fn evaluate_value(value: &Value, env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
-    match value {
-        &Value::Symbol(ref v) => {
-            match env.borrow().get(v) {
-                Some(val) => Ok(val),
-                None => runtime_error!("Identifier not found: {:?}", value)
-            }
-        },
-        &Value::Integer(v) => Ok(Value::Integer(v)),
-        &Value::Boolean(v) => Ok(Value::Boolean(v)),
-        &Value::String(ref v) => Ok(Value::String(v.clone())),
-        &Value::List(ref vec) => {
-            if vec.len() > 0 {
-                evaluate_expression(vec, env.clone())
+// Evaluate the body of a lambda, let or similar form.  Definitions at the start of the body are bound
+// with letrec* semantics: all of the names are bound first, and then initialized in order.
+fn evaluate_body(body: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let mut definitions = Vec::new();
+    let mut start = 0;
+    while start < body.len() && is_definition(&body[start], &env) {
+        collect_definitions(&body[start], &mut definitions);
+        start += 1;
+    }
+    let expressions = &body[start..];
+    if let Some(def) = expressions.iter().find(|v| is_definition(v, &env)) {
+        runtime_error!("Definitions must come before any expressions in a body: {:?}", def);
+    }
+    if expressions.len() == 0 {
+        runtime_error!("Body must contain at least one expression after its definitions");
+    }
+
+    for def in definitions.iter() {
+        if let Some(name) = defined_name(def) {
+            env.borrow_mut().declare(name);
+        }
+    }
+    for def in definitions.iter() {
+        try!(evaluate_value(def, env.clone()));
+    }
+    evaluate_values(expressions, env)
+}
+
+// Definitions are define forms, or begin forms containing only definitions
+fn is_definition(value: &Value, env: &Rc<RefCell<Environment>>) -> bool {
+    match *value {
+        Value::List(ref list) if list.len() > 0 => {
+            match list[0] {
+                Value::Symbol(ref s) if keyword(s, env).is_none() => false,
+                Value::Symbol(ref s) if s == "define" || s == "define-syntax-rule" || s == "define-record-type" => true,
+                Value::Symbol(ref s) if s == "begin" => list.len() > 1 && (&list[1..]).iter().all(|v| is_definition(v, env)),
+                _ => false
+            }
+        },
+        _ => false
+    }
+}
+
+// Flatten any begin forms, which are spliced into the surrounding body
+fn collect_definitions(value: &Value, definitions: &mut Vec<Value>) {
+    match *value {
+        Value::List(ref list) if list[0] == Value::Symbol("begin".to_string()) => {
+            for v in (&list[1..]).iter() {
+                collect_definitions(v, definitions);
+            }
+        },
+        _ => definitions.push(value.clone())
+    }
+}
+
+// The variable bound by a define form, either (define <name> ...) or (define (<name> <args>) ...)
+fn defined_name(def: &Value) -> Option<String> {
+    match *def {
+        Value::List(ref list) if list.len() > 1 && list[0] == Value::Symbol("define".to_string()) => {
+            match list[1] {
+                Value::Symbol(ref name) => Some(name.clone()),
+                Value::List(ref sig) if sig.len() > 0 => match sig[0] {
+                    Value::Symbol(ref name) => Some(name.clone()),
+                    _ => None
+                },
+                _ => None
+            }
+        },
+        _ => None
+    }
+}
+
+pub fn evaluate_value(value: &Value, env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    match value {
+        &Value::Symbol(ref v) => {
+            if keyword(v, &env).is_some() {
+                runtime_error!("Syntactic keyword can't be used as a variable: {}", v);
+            }
+            let env = env.borrow();
+            match env.get(v) {
+                Some(val) => Ok(val),
+                None if env.is_unassigned(v) => runtime_error!("Variable used before its definition: {}", v),
+                None => runtime_error!("Identifier not found: {:?}", value)
+            }
+        },
+        &Value::Integer(v) => Ok(Value::Integer(v)),
+        &Value::Rational(n, d) => Ok(Value::Rational(n, d)),
+        &Value::Float(v) => Ok(Value::Float(v)),
+        &Value::Complex(re, im) => Ok(Value::Complex(re, im)),
+        &Value::Boolean(v) => Ok(Value::Boolean(v)),
+        &Value::Char(v) => Ok(Value::Char(v)),
+        &Value::Eof => Ok(Value::Eof),
+        &Value::String(ref v) => Ok(Value::String(v.clone())),
+        &Value::List(ref vec) => {
+            if vec.len() > 0 {
+                evaluate_expression(vec, env.clone())
             } else {
                 Ok(Value::null())
             }
         },
+        &Value::Vector(ref v) => Ok(Value::Vector(v.clone())),
+        &Value::Bytevector(ref b) => Ok(Value::Bytevector(b.clone())),
         &Value::Procedure(ref v) => Ok(Value::Procedure(v.clone())),
         &Value::Macro(ref a, ref b) => Ok(Value::Macro(a.clone(), b.clone())),
+        &Value::RecordType(ref t) => Ok(Value::RecordType(t.clone())),
+        &Value::Record(ref r) => Ok(Value::Record(r.clone())),
+        &Value::Environment(ref e) => Ok(Value::Environment(e.clone())),
+        &Value::Port(ref p) => Ok(Value::Port(p.clone())),
         &Value::CustomType(ref c) => Ok(Value::CustomType(c.clone()))
     }
 }
@@ -484,7 +1555,12 @@ fn quote_value(value: &Value, quasi: bool, env: Rc<RefCell<Environment>>) -> Res
     match value {
         &Value::Symbol(ref v) => Ok(Value::Symbol(v.clone())),
         &Value::Integer(v) => Ok(Value::Integer(v)),
+        &Value::Rational(n, d) => Ok(Value::Rational(n, d)),
+        &Value::Float(v) => Ok(Value::Float(v)),
+        &Value::Complex(re, im) => Ok(Value::Complex(re, im)),
         &Value::Boolean(v) => Ok(Value::Boolean(v)),
+        &Value::Char(v) => Ok(Value::Char(v)),
+        &Value::Eof => Ok(Value::Eof),
         &Value::String(ref v) => Ok(Value::String(v.clone())),
         &Value::List(ref vec) => {
             // check if we are unquoting inside a quasiquote
@@ -506,8 +1582,14 @@ fn quote_value(value: &Value, quasi: bool, env: Rc<RefCell<Environment>>) -> Res
                 Ok(Value::List(new_vec))
             }
         },
+        &Value::Vector(ref v) => Ok(Value::Vector(v.clone())),
+        &Value::Bytevector(ref b) => Ok(Value::Bytevector(b.clone())),
         &Value::Procedure(ref v) => Ok(Value::Procedure(v.clone())),
         &Value::Macro(ref a, ref b) => Ok(Value::Macro(a.clone(), b.clone())),
+        &Value::RecordType(ref t) => Ok(Value::RecordType(t.clone())),
+        &Value::Record(ref r) => Ok(Value::Record(r.clone())),
+        &Value::Environment(ref e) => Ok(Value::Environment(e.clone())),
+        &Value::Port(ref p) => Ok(Value::Port(p.clone())),
         &Value::CustomType(ref c) => Ok(Value::CustomType(c.clone()))
     }
 }
@@ -516,45 +1598,154 @@ fn evaluate_expression(values: &Vec<Value>, env: Rc<RefCell<Environment>>) -> Re
     if values.len() == 0 {
         runtime_error!("Can't evaluate an empty expression: {:?}", values);
     }
+    if let Value::Symbol(ref name) = values[0] {
+        if let Some(form) = keyword(name, &env) {
+            return form(&values[1..], env);
+        }
+    }
     let first = try!(evaluate_value(&values[0], env.clone()));
     match first {
-        Value::Procedure(f) => apply_function(&f, &values[1..], env.clone()),
+        Value::Procedure(f) => {
+            let args = try!(evaluate_arguments(&values[1..], env.clone()));
+            apply_function(&f, args, env.clone())
+        },
         Value::Macro(a, b) => expand_macro(a, b, &values[1..], env.clone()),
         _ => runtime_error!("First element in an expression must be a procedure: {:?}", first)
     }
 }
 
-fn apply_function(func: &Function, args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+pub fn evaluate_arguments(values: &[Value], env: Rc<RefCell<Environment>>) -> Result<Vec<Value>, RuntimeError> {
+    values.iter().map(|v| evaluate_value(v, env.clone())).collect()
+}
+
+// Call a procedure with arguments that have already been evaluated
+pub fn apply_function(func: &Function, args: Vec<Value>, env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
     match func {
         &Function::Native(ref native_fn) => {
-            native_fn(args, env)
+            native_fn(&args, env)
         },
-        &Function::Scheme(ref arg_names, ref body, ref func_env) => {
+        &Function::Scheme(ref arg_names, ref body, ref func_env, _) => {
             if arg_names.len() != args.len() {
                 runtime_error!("Must supply exactly {} arguments to function: {:?}", arg_names.len(), args);
             }
 
             // create a new, child environment for the procedure and define the arguments as local variables
             let proc_env = Environment::new_child(func_env.clone());
-            for (name, arg) in arg_names.iter().zip(args.iter()) {
-                let val = try!(evaluate_value(arg, env.clone()));
-                try!(proc_env.borrow_mut().define(name.clone(), val));
+            for (name, arg) in arg_names.iter().zip(args.into_iter()) {
+                try!(proc_env.borrow_mut().define(name.clone(), arg));
             }
 
             // evaluate procedure body with new environment with procedure environment as parent
             let inner_env = Environment::new_child(proc_env);
-            evaluate_values(&body, inner_env)
+            evaluate_body(&body, inner_env)
+        },
+        &Function::Parameter(ref param) => {
+            if args.len() != 0 {
+                runtime_error!("Must supply exactly zero arguments to a parameter: {:?}", args);
+            }
+            Ok(param.value.borrow().clone())
         }
     }
 }
 
 fn expand_macro(arg_names: Vec<String>, body: Vec<Value>, args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let expanded = try!(substitute_macro(&arg_names, &body, args));
+    evaluate_values(&expanded, env)
+}
+
+fn substitute_macro(arg_names: &[String], body: &[Value], args: &[Value]) -> Result<Vec<Value>, RuntimeError> {
+    if arg_names.len() != args.len() {
+        runtime_error!("Must supply exactly {} arguments to macro: {:?}", arg_names.len(), args);
+    }
     let mut substitutions = HashMap::new();
     for (name, arg) in arg_names.iter().zip(args.iter()) {
         substitutions.insert(name.clone(), arg.clone());
     }
-    let expanded = try!(expand_macro_substitute_values(&body, substitutions));
-    evaluate_values(&expanded, env)
+    expand_macro_substitute_values(body, substitutions)
+}
+
+// Expand a macro use once without evaluating the result, or return None if the expression isn't a macro use
+fn macroexpand_1(value: &Value, env: Rc<RefCell<Environment>>) -> Result<Option<Value>, RuntimeError> {
+    let list = match *value {
+        Value::List(ref list) if list.len() > 0 => list,
+        _ => return Ok(None)
+    };
+    let name = match list[0] {
+        Value::Symbol(ref name) if keyword(name, &env).is_none() => name,
+        _ => return Ok(None)
+    };
+    let binding = env.borrow().get(name);
+    match binding {
+        Some(Value::Macro(ref arg_names, ref body)) => {
+            let mut expanded = try!(substitute_macro(arg_names, body, &list[1..]));
+            if expanded.len() == 1 {
+                Ok(expanded.pop())
+            } else {
+                expanded.insert(0, Value::Symbol("begin".to_string()));
+                Ok(Some(Value::List(expanded)))
+            }
+        },
+        _ => Ok(None)
+    }
+}
+
+// Expand a macro use repeatedly, until the expression is no longer a macro use
+fn macroexpand(value: &Value, env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let mut res = value.clone();
+    loop {
+        match try!(macroexpand_1(&res, env.clone())) {
+            Some(expanded) => res = expanded,
+            None => return Ok(res)
+        }
+    }
+}
+
+// Expand all macro uses in an expression, including within its subexpressions
+fn expand_all(value: &Value, env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let expanded = try!(macroexpand(value, env.clone()));
+    let list = match expanded {
+        Value::List(ref list) if list.len() > 0 => list,
+        _ => return Ok(expanded.clone())
+    };
+    let keyword = match list[0] {
+        Value::Symbol(ref s) if keyword(s, &env).is_some() => s.as_str(),
+        _ => ""
+    };
+    let expand_values = |values: &[Value]| -> Result<Vec<Value>, RuntimeError> {
+        values.iter().map(|v| expand_all(v, env.clone())).collect()
+    };
+    let mut res = Vec::new();
+    match keyword {
+        // quoted data and macro templates are left alone
+        "quote" | "quasiquote" | "define-syntax-rule" | "define-record-type" => return Ok(expanded.clone()),
+        // the formals and names being defined aren't expressions
+        "lambda" | "λ" | "define" if list.len() > 1 => {
+            res.extend((&list[..2]).iter().cloned());
+            res.extend(try!(expand_values(&list[2..])));
+        },
+        // only the initial values of bindings are expressions
+        "let" | "parameterize" if list.len() > 1 => {
+            res.push(list[0].clone());
+            match list[1] {
+                Value::List(ref bindings) => {
+                    let mut new_bindings = Vec::new();
+                    for binding in bindings.iter() {
+                        match *binding {
+                            Value::List(ref pair) if pair.len() == 2 => {
+                                new_bindings.push(Value::List(vec![pair[0].clone(), try!(expand_all(&pair[1], env.clone()))]));
+                            },
+                            _ => new_bindings.push(binding.clone())
+                        }
+                    }
+                    res.push(Value::List(new_bindings));
+                },
+                ref other => res.push(other.clone())
+            }
+            res.extend(try!(expand_values(&list[2..])));
+        },
+        _ => res = try!(expand_values(list))
+    }
+    Ok(Value::List(res))
 }
 
 fn expand_macro_substitute_values(values: &[Value], substitutions: HashMap<String,Value>) -> Result<Vec<Value>, RuntimeError> {
@@ -584,7 +1775,11 @@ fn native_define(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value,
     }
     let (name, val) = match args[0] {
         Value::Symbol(ref name) => {
-            let val = try!(evaluate_value(&args[1], env.clone()));
+            // anonymous procedures take on the name they're first defined with
+            let val = match try!(evaluate_value(&args[1], env.clone())) {
+                Value::Procedure(Function::Scheme(a, b, e, None)) => Value::Procedure(Function::Scheme(a, b, e, Some(name.clone()))),
+                val => val
+            };
             (name, val)
         },
         Value::List(ref list) => {
@@ -601,7 +1796,7 @@ fn native_define(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value,
                     }).collect();
                     let arg_names = try!(res);
                     let body = (&args[1..]).to_vec();
-                    let val = Value::Procedure(Function::Scheme(arg_names, body, env.clone()));
+                    let val = Value::Procedure(Function::Scheme(arg_names, body, env.clone(), Some(name.clone())));
                     (name, val)
                 },
                 _ => runtime_error!("Must supply a symbol in list part of define: {:?}", list)
@@ -645,6 +1840,151 @@ fn native_define_syntax_rule(args: &[Value], env: Rc<RefCell<Environment>>) -> R
     Ok(Value::null())
 }
 
+fn native_define_record_type(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() < 2 {
+        runtime_error!("Must supply at least two arguments to define-record-type: {:?}", args);
+    }
+
+    // (define-record-type <type name> (<constructor> <field> ...) <predicate> (<field> <accessor> [<modifier>]) ...)
+    let type_name = match args[0] {
+        Value::Symbol(ref s) => s.clone(),
+        _ => runtime_error!("Unexpected value for type name in define-record-type: {:?}", args[0])
+    };
+    let mut field_names = Vec::new();
+    for spec in args.iter().skip(3) {
+        match *spec {
+            Value::List(ref list) if list.len() >= 1 && list.len() <= 3 => {
+                match list[0] {
+                    Value::Symbol(ref s) => field_names.push(s.clone()),
+                    _ => runtime_error!("Unexpected value for field name in define-record-type: {:?}", spec)
+                }
+            },
+            _ => runtime_error!("Unexpected field spec in define-record-type: {:?}", spec)
+        }
+    }
+
+    // records print using the type name without the conventional angle brackets
+    let printed_name = type_name.trim_start_matches('<').trim_end_matches('>').to_string();
+    let rtype = Rc::new(RecordType { name: printed_name, fields: field_names.clone() });
+    let mut definitions = vec![(type_name, Value::RecordType(rtype.clone()))];
+
+    // the constructor is either a list of its name and the fields it initializes, or just a name, in which
+    // case it initializes all of the fields in order; #f means no constructor is defined
+    let (ctor_name, ctor_fields) = match args[1] {
+        Value::Symbol(ref s) => (Some(s.clone()), field_names.clone()),
+        Value::Boolean(false) => (None, vec![]),
+        Value::List(ref list) if list.len() >= 1 => {
+            let res: Result<Vec<String>, RuntimeError> = list.iter().map(|i| match *i {
+                Value::Symbol(ref s) => Ok(s.clone()),
+                _ => runtime_error!("Unexpected argument in define-record-type constructor: {:?}", i)
+            }).collect();
+            let mut names = try!(res);
+            let name = names.remove(0);
+            (Some(name), names)
+        },
+        _ => runtime_error!("Unexpected value for constructor in define-record-type: {:?}", args[1])
+    };
+    if let Some(name) = ctor_name {
+        let mut indices = Vec::new();
+        for field in ctor_fields.iter() {
+            match field_names.iter().position(|f| f == field) {
+                Some(i) => indices.push(i),
+                None => runtime_error!("Unknown field in define-record-type constructor: {}", field)
+            }
+        }
+        let ctor_type = rtype.clone();
+        let ctor_name = name.clone();
+        let ctor = move |args: &[Value], _env: Rc<RefCell<Environment>>| -> Result<Value, RuntimeError> {
+            if args.len() != indices.len() {
+                runtime_error!("Must supply exactly {} arguments to {}: {:?}", indices.len(), ctor_name, args);
+            }
+            let mut fields = vec![Value::null(); ctor_type.fields.len()];
+            for (i, arg) in indices.iter().zip(args.iter()) {
+                fields[*i] = arg.clone();
+            }
+            Ok(Value::Record(Record { rtype: ctor_type.clone(), fields: Rc::new(RefCell::new(fields)) }))
+        };
+        definitions.push((name, Value::Procedure(Function::Native(Rc::new(Box::new(ctor))))));
+    }
+
+    if args.len() > 2 {
+        match args[2] {
+            Value::Symbol(ref name) => {
+                let pred_type = rtype.clone();
+                let pred_name = name.clone();
+                let pred = move |args: &[Value], _env: Rc<RefCell<Environment>>| -> Result<Value, RuntimeError> {
+                    if args.len() != 1 {
+                        runtime_error!("Must supply exactly one argument to {}: {:?}", pred_name, args);
+                    }
+                    match args[0] {
+                        Value::Record(ref r) => Ok(Value::Boolean(Rc::ptr_eq(&r.rtype, &pred_type))),
+                        _ => Ok(Value::Boolean(false))
+                    }
+                };
+                definitions.push((name.clone(), Value::Procedure(Function::Native(Rc::new(Box::new(pred))))));
+            },
+            _ => runtime_error!("Unexpected value for predicate in define-record-type: {:?}", args[2])
+        }
+    }
+
+    for (i, spec) in args.iter().skip(3).enumerate() {
+        let list = match *spec {
+            Value::List(ref list) => list,
+            _ => runtime_error!("Unexpected field spec in define-record-type: {:?}", spec)
+        };
+        if list.len() > 1 {
+            let accessor_name = match list[1] {
+                Value::Symbol(ref s) => s.clone(),
+                _ => runtime_error!("Unexpected value for accessor in define-record-type: {:?}", spec)
+            };
+            let accessor = record_accessor(rtype.clone(), i, accessor_name.clone());
+            definitions.push((accessor_name, accessor));
+        }
+        if list.len() > 2 {
+            let modifier_name = match list[2] {
+                Value::Symbol(ref s) => s.clone(),
+                _ => runtime_error!("Unexpected value for modifier in define-record-type: {:?}", spec)
+            };
+            let modifier = record_modifier(rtype.clone(), i, modifier_name.clone());
+            definitions.push((modifier_name, modifier));
+        }
+    }
+
+    for (name, val) in definitions.into_iter() {
+        try!(env.borrow_mut().define(name, val));
+    }
+    Ok(Value::null())
+}
+
+fn record_accessor(rtype: Rc<RecordType>, index: usize, name: String) -> Value {
+    let accessor = move |args: &[Value], _env: Rc<RefCell<Environment>>| -> Result<Value, RuntimeError> {
+        if args.len() != 1 {
+            runtime_error!("Must supply exactly one argument to {}: {:?}", name, args);
+        }
+        match args[0] {
+            Value::Record(ref r) if Rc::ptr_eq(&r.rtype, &rtype) => Ok(r.fields.borrow()[index].clone()),
+            ref v => runtime_error!("Must supply a {} record to {}: {:?}", rtype.name, name, v)
+        }
+    };
+    Value::Procedure(Function::Native(Rc::new(Box::new(accessor))))
+}
+
+fn record_modifier(rtype: Rc<RecordType>, index: usize, name: String) -> Value {
+    let modifier = move |args: &[Value], _env: Rc<RefCell<Environment>>| -> Result<Value, RuntimeError> {
+        if args.len() != 2 {
+            runtime_error!("Must supply exactly two arguments to {}: {:?}", name, args);
+        }
+        match args[0] {
+            Value::Record(ref r) if Rc::ptr_eq(&r.rtype, &rtype) => {
+                r.fields.borrow_mut()[index] = args[1].clone();
+                Ok(Value::null())
+            },
+            ref v => runtime_error!("Must supply a {} record to {}: {:?}", rtype.name, name, v)
+        }
+    };
+    Value::Procedure(Function::Native(Rc::new(Box::new(modifier))))
+}
+
 fn native_begin(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
     if args.len() < 1 {
         runtime_error!("Must supply at least one argument to begin: {:?}", args);
@@ -684,7 +2024,7 @@ fn native_let(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, Ru
     // evaluate let statement body with new environment with let environment as parent
     let inner_env = Environment::new_child(let_env);
     let body = &args[1..];
-    evaluate_values(body, inner_env)
+    evaluate_body(body, inner_env)
 }
 
 fn native_set(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
@@ -715,7 +2055,7 @@ fn native_lambda(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value,
         _ => runtime_error!("Unexpected value for arguments in lambda: {:?}", args)
     };
     let body = (&args[1..]).to_vec();
-    Ok(Value::Procedure(Function::Scheme(arg_names, body, env.clone())))
+    Ok(Value::Procedure(Function::Scheme(arg_names, body, env.clone(), None)))
 }
 
 fn native_if(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
@@ -729,343 +2069,3532 @@ fn native_if(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, Run
     }
 }
 
-fn native_plus(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
-    if args.len() < 2 {
-        runtime_error!("Must supply at least two arguments to +: {:?}", args);
+fn native_make_parameter(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() < 1 || args.len() > 2 {
+        runtime_error!("Must supply one or two arguments to make-parameter: {:?}", args);
     }
-    let mut sum = 0;
-    for n in args.iter() {
-        let v = try!(evaluate_value(n, env.clone()));
-        match v {
-            Value::Integer(x) => sum += x,
-            _ => runtime_error!("Unexpected value during +: {:?}", n)
-        };
+    let val = args[0].clone();
+    let converter = if args.len() == 2 {
+        match args[1].clone() {
+            Value::Procedure(f) => Some(f),
+            _ => runtime_error!("Converter passed to make-parameter must be a procedure: {:?}", args)
+        }
+    } else {
+        None
     };
-    Ok(Value::Integer(sum))
-}
 
-fn native_minus(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
-    if args.len() != 2 {
-        runtime_error!("Must supply exactly two arguments to -: {:?}", args);
-    }
-    let l = try!(evaluate_value(&args[0], env.clone()));
-    let r = try!(evaluate_value(&args[1], env.clone()));
-    let mut result = match l {
-        Value::Integer(x) => x,
-        _ => runtime_error!("Unexpected value during -: {:?}", args)
-    };
-    result -= match r {
-        Value::Integer(x) => x,
-        _ => runtime_error!("Unexpected value during -: {:?}", args)
+    // the converter is also applied to the initial value
+    let initial = match converter {
+        Some(ref f) => try!(apply_function(f, vec![val], env.clone())),
+        None => val
     };
-    Ok(Value::Integer(result))
+    let param = Parameter { value: RefCell::new(initial), converter: converter };
+    Ok(Value::Procedure(Function::Parameter(Rc::new(param))))
 }
 
-fn native_multiply(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+fn native_parameterize(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
     if args.len() < 2 {
-        runtime_error!("Must supply at least two arguments to *: {:?}", args);
+        runtime_error!("Must supply at least two arguments to parameterize: {:?}", args);
     }
-    let mut product = 1;
-    for n in args.iter() {
-        let v = try!(evaluate_value(n, env.clone()));
-        match v {
-            Value::Integer(x) => product *= x,
-            _ => runtime_error!("Unexpected value during *: {:?}", n)
-        };
-    };
-    Ok(Value::Integer(product))
-}
 
-fn native_divide(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
-    if args.len() != 2 {
-        runtime_error!("Must supply exactly two arguments to /: {:?}", args);
-    }
-    let l = try!(evaluate_value(&args[0], env.clone()));
-    let r = try!(evaluate_value(&args[1], env.clone()));
-    let mut result = match l {
-        Value::Integer(x) => x,
-        _ => runtime_error!("Unexpected value during /: {:?}", args)
-    };
-    result /= match r {
-        Value::Integer(x) => x,
-        _ => runtime_error!("Unexpected value during /: {:?}", args)
+    // evaluate all parameters and their new values before rebinding any of them
+    let mut bindings = Vec::new();
+    match args[0] {
+        Value::List(ref list) => {
+            for i in list.iter() {
+                match *i {
+                    Value::List(ref entry) => {
+                        if entry.len() != 2 {
+                            runtime_error!("parameterize bindings must have exactly 2 params: {:?}", entry);
+                        }
+                        let param = match try!(evaluate_value(&entry[0], env.clone())) {
+                            Value::Procedure(Function::Parameter(p)) => p,
+                            _ => runtime_error!("Not a parameter in parameterize: {:?}", entry[0])
+                        };
+                        let raw = try!(evaluate_value(&entry[1], env.clone()));
+                        let val = match param.converter {
+                            Some(ref f) => try!(apply_function(f, vec![raw], env.clone())),
+                            None => raw
+                        };
+                        bindings.push((param, val));
+                    },
+                    _ => runtime_error!("Unexpected value inside bindings in parameterize: {:?}", i)
+                }
+            }
+        },
+        _ => runtime_error!("Unexpected value for bindings in parameterize: {:?}", args)
     };
-    Ok(Value::Integer(result))
-}
 
-fn native_lessthan(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
-    if args.len() != 2 {
-        runtime_error!("Must supply exactly two arguments to <: {:?}", args);
-    }
-    let l_raw = try!(evaluate_value(&args[0], env.clone()));
-    let r_raw = try!(evaluate_value(&args[1], env.clone()));
-    let l = match l_raw {
-        Value::Integer(x) => x,
-        _ => runtime_error!("Unexpected value during <: {:?}", args)
-    };
-    let r = match r_raw {
-        Value::Integer(x) => x,
-        _ => runtime_error!("Unexpected value during <: {:?}", args)
-    };
-    Ok(Value::Boolean(l < r))
-}
+    let saved: Vec<Value> = bindings.iter().map(|&(ref param, ref val)| {
+        param.value.replace(val.clone())
+    }).collect();
 
-fn native_greaterthan(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
-    if args.len() != 2 {
-        runtime_error!("Must supply exactly two arguments to >: {:?}", args);
+    let inner_env = Environment::new_child(env.clone());
+    let res = evaluate_body(&args[1..], inner_env);
+
+    // restore the old values whether or not the body succeeded
+    for (&(ref param, _), old) in bindings.iter().zip(saved.into_iter()).rev() {
+        *param.value.borrow_mut() = old;
     }
-    let l_raw = try!(evaluate_value(&args[0], env.clone()));
-    let r_raw = try!(evaluate_value(&args[1], env.clone()));
-    let l = match l_raw {
-        Value::Integer(x) => x,
-        _ => runtime_error!("Unexpected value during >: {:?}", args)
-    };
-    let r = match r_raw {
-        Value::Integer(x) => x,
-        _ => runtime_error!("Unexpected value during >: {:?}", args)
-    };
-    Ok(Value::Boolean(l > r))
+    res
 }
 
-fn native_equal(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
-    if args.len() != 2 {
-        runtime_error!("Must supply exactly two arguments to =: {:?}", args);
+// Numbers as arithmetic sees them: integers and fractions stay exact until they meet a float
+impl Number {
+    fn to_value(self) -> Value {
+        match self {
+            Number::Exact(i) => Value::Integer(i),
+            Number::Rational(n, d) => Value::Rational(n, d),
+            Number::Inexact(f) => Value::Float(f)
+        }
     }
-    let l_raw = try!(evaluate_value(&args[0], env.clone()));
-    let r_raw = try!(evaluate_value(&args[1], env.clone()));
-    let l = match l_raw {
-        Value::Integer(x) => x,
-        _ => runtime_error!("Unexpected value during =: {:?}", args)
-    };
-    let r = match r_raw {
-        Value::Integer(x) => x,
-        _ => runtime_error!("Unexpected value during =: {:?}", args)
-    };
-    Ok(Value::Boolean(l == r))
-}
 
-fn native_and(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
-    let mut res = Value::Boolean(true);
-    for n in args.iter() {
-        let v = try!(evaluate_value(n, env.clone()));
-        match v {
-            Value::Boolean(false) => return Ok(Value::Boolean(false)),
-            _ => res = v
+    fn is_exact(self) -> bool {
+        match self {
+            Number::Exact(_) | Number::Rational(_, _) => true,
+            Number::Inexact(_) => false
         }
     }
-    Ok(res)
-}
 
-fn native_or(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
-    for n in args.iter() {
-        let v = try!(evaluate_value(n, env.clone()));
-        match v {
-            Value::Boolean(false) => (),
-            _ => return Ok(v)
+    // The numerator and denominator of an exact number
+    fn fraction(self) -> Option<(i128, i128)> {
+        match self {
+            Number::Exact(i) => Some((i as i128, 1)),
+            Number::Rational(n, d) => Some((n as i128, d as i128)),
+            Number::Inexact(_) => None
         }
     }
-    Ok(Value::Boolean(false))
 }
 
-fn native_null(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
-    if args.len() != 1 {
-        runtime_error!("Must supply exactly one argument to null?: {:?}", args);
-    }
-    let v = try!(evaluate_value(&args[0], env.clone()));
-    match v {
-        Value::List(l) => Ok(Value::Boolean(l.len() == 0)),
-        _ => Ok(Value::Boolean(false))
+fn expect_number(name: &str, v: &Value) -> Result<Number, RuntimeError> {
+    match *v {
+        Value::Integer(i) => Ok(Number::Exact(i)),
+        Value::Rational(n, d) => Ok(Number::Rational(n, d)),
+        Value::Float(f) => Ok(Number::Inexact(f)),
+        Value::Complex(_, _) => runtime_error!("Must supply a real number to {}: {:?}", name, v),
+        _ => runtime_error!("Must supply a number to {}: {:?}", name, v)
     }
 }
 
-fn native_list(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
-    let res: Result<Vec<Value>, RuntimeError> = args.iter().map(|n| evaluate_value(n, env.clone())).collect();
-    let elements = try!(res);
-    Ok(Value::List(elements))
-}
+// The real and imaginary parts of a number
+type Complex = (Number, Number);
 
-fn native_car(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
-    if args.len() != 1 {
-        runtime_error!("Must supply exactly one argument to car: {:?}", args);
-    }
-    let v = try!(evaluate_value(&args[0], env.clone()));
-    match v {
-        Value::List(mut l) => {
-            if l.len() > 0 {
-                Ok(l.remove(0))
-            } else {
-                runtime_error!("Can't run car on an empty list")
-            }
-        }
-        _ => runtime_error!("Must supply a list to car")
+fn expect_complex(name: &str, v: &Value) -> Result<Complex, RuntimeError> {
+    match *v {
+        Value::Complex(re, im) => Ok((re, im)),
+        _ => Ok((try!(expect_number(name, v)), Number::Exact(0)))
     }
 }
 
-fn native_cdr(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
-    if args.len() != 1 {
-        runtime_error!("Must supply exactly one argument to cdr: {:?}", args);
+// Numbers with an exact zero imaginary part are real
+fn complex_value((re, im): Complex) -> Value {
+    match im {
+        Number::Exact(0) => re.to_value(),
+        _ => Value::Complex(re, im)
     }
-    let v = try!(evaluate_value(&args[0], env.clone()));
-    match v {
-        Value::List(mut l) => {
-            if l.len() > 0 {
-                l.remove(0);
-                Ok(Value::List(l))
-            } else {
-                runtime_error!("Can't run cdr on an empty list")
-            }
-        }
-        _ => runtime_error!("Must supply a list to cdr")
+}
+
+// Integers, including floats with nothing after the decimal point
+fn expect_integer(name: &str, v: &Value) -> Result<Number, RuntimeError> {
+    match *v {
+        Value::Integer(i) => Ok(Number::Exact(i)),
+        Value::Float(f) if f.is_finite() && f.fract() == 0.0 => Ok(Number::Inexact(f)),
+        _ => runtime_error!("Must supply an integer to {}: {:?}", name, v)
     }
 }
 
-fn native_cons(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
-    if args.len() != 2 {
-        runtime_error!("Must supply exactly two arguments to cons: {:?}", args);
+fn expect_numbers(name: &str, args: &[Value]) -> Result<Vec<Number>, RuntimeError> {
+    args.iter().map(|v| expect_number(name, v)).collect()
+}
+
+// Combine two numbers, with the checked integer operation while both are exact, since there are no bignums
+fn arithmetic(name: &str, a: Number, b: Number, exact: fn(i64, i64) -> Option<i64>, inexact: fn(f64, f64) -> f64) -> Result<Number, RuntimeError> {
+    match (a, b) {
+        (Number::Exact(x), Number::Exact(y)) => match exact(x, y) {
+            Some(res) => Ok(Number::Exact(res)),
+            None => runtime_error!("Integer overflow in {}: {} and {}", name, x, y)
+        },
+        _ => Ok(Number::Inexact(inexact(a.to_f64(), b.to_f64())))
     }
+}
 
-    let first = try!(evaluate_value(&args[0], env.clone()));
-    let second = try!(evaluate_value(&args[1], env.clone()));
-    match second {
-        Value::List(elements) => {
-            let mut new_elements = vec![first];
-            for e in elements.into_iter() {
-                new_elements.push(e);
+// Combine two numbers as fractions while both are exact.  The i128 products of i64 numerators and denominators
+// can't overflow, so only a result too big to reduce back into an i64 fraction does
+fn fraction_arithmetic(name: &str, a: Number, b: Number, exact: fn((i128, i128), (i128, i128)) -> (i128, i128),
+                       inexact: fn(f64, f64) -> f64) -> Result<Number, RuntimeError> {
+    match (a.fraction(), b.fraction()) {
+        (Some(x), Some(y)) => {
+            let (n, d) = exact(x, y);
+            match Number::rational(n, d) {
+                Some(res) => Ok(res),
+                None => runtime_error!("Integer overflow in {}: {} and {}", name, format_real(a), format_real(b))
             }
-            return Ok(Value::List(new_elements))
-        }
-        _ => runtime_error!("Second argument to cons must be a list: {:?}", second)
+        },
+        _ => Ok(Number::Inexact(inexact(a.to_f64(), b.to_f64())))
     }
 }
 
-fn native_append(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
-    if args.len() != 2 {
-        runtime_error!("Must supply exactly two arguments to append: {:?}", args);
+fn divide(a: Number, b: Number) -> Result<Number, RuntimeError> {
+    if a.is_exact() && b == Number::Exact(0) {
+        runtime_error!("Division by zero in /");
     }
+    fraction_arithmetic("/", a, b, |(n1, d1), (n2, d2)| (n1 * d2, d1 * n2), |x, y| x / y)
+}
 
-    let first = try!(evaluate_value(&args[0], env.clone()));
-    let second = try!(evaluate_value(&args[1], env.clone()));
-    let mut first_vec = match first {
-        Value::List(elements) => elements,
-        _ => runtime_error!("First argument to append must be a list: {:?}", first)
-    };
-    let second_vec = match second {
-        Value::List(elements) => elements,
-        _ => runtime_error!("Second argument to append must be a list: {:?}", second)
-    };
-    for e in second_vec.into_iter() {
-        first_vec.push(e);
-    }
-    return Ok(Value::List(first_vec))
+fn add(name: &str, a: Number, b: Number) -> Result<Number, RuntimeError> {
+    fraction_arithmetic(name, a, b, |(n1, d1), (n2, d2)| (n1 * d2 + n2 * d1, d1 * d2), |x, y| x + y)
 }
 
-fn native_quote(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
-    if args.len() != 1 {
-        runtime_error!("Must supply exactly one argument to quote: {:?}", args);
-    }
-    quote_value(&args[0], false, env.clone())
+fn subtract(name: &str, a: Number, b: Number) -> Result<Number, RuntimeError> {
+    fraction_arithmetic(name, a, b, |(n1, d1), (n2, d2)| (n1 * d2 - n2 * d1, d1 * d2), |x, y| x - y)
 }
 
-fn native_quasiquote(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
-    if args.len() != 1 {
-        runtime_error!("Must supply exactly one argument to quasiquote: {:?}", args);
-    }
-    quote_value(&args[0], true, env.clone())
+fn multiply(name: &str, a: Number, b: Number) -> Result<Number, RuntimeError> {
+    fraction_arithmetic(name, a, b, |(n1, d1), (n2, d2)| (n1 * n2, d1 * d2), |x, y| x * y)
 }
 
-fn native_error(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+fn complex_add(name: &str, a: Complex, b: Complex) -> Result<Complex, RuntimeError> {
+    Ok((try!(add(name, a.0, b.0)), try!(add(name, a.1, b.1))))
+}
+
+fn complex_subtract(name: &str, a: Complex, b: Complex) -> Result<Complex, RuntimeError> {
+    Ok((try!(subtract(name, a.0, b.0)), try!(subtract(name, a.1, b.1))))
+}
+
+fn complex_multiply(name: &str, a: Complex, b: Complex) -> Result<Complex, RuntimeError> {
+    // keep real products real, rather than picking up an inexact zero imaginary part
+    if a.1 == Number::Exact(0) && b.1 == Number::Exact(0) {
+        return Ok((try!(multiply(name, a.0, b.0)), Number::Exact(0)));
+    }
+    let re = try!(subtract(name, try!(multiply(name, a.0, b.0)), try!(multiply(name, a.1, b.1))));
+    let im = try!(add(name, try!(multiply(name, a.0, b.1)), try!(multiply(name, a.1, b.0))));
+    Ok((re, im))
+}
+
+fn complex_divide(a: Complex, b: Complex) -> Result<Complex, RuntimeError> {
+    if a.1 == Number::Exact(0) && b.1 == Number::Exact(0) {
+        return Ok((try!(divide(a.0, b.0)), Number::Exact(0)));
+    }
+    // (a + bi) / (c + di) = ((ac + bd) + (bc - ad)i) / (c^2 + d^2)
+    let denominator = try!(add("/", try!(multiply("/", b.0, b.0)), try!(multiply("/", b.1, b.1))));
+    let re = try!(add("/", try!(multiply("/", a.0, b.0)), try!(multiply("/", a.1, b.1))));
+    let im = try!(subtract("/", try!(multiply("/", a.1, b.0)), try!(multiply("/", a.0, b.1))));
+    Ok((try!(divide(re, denominator)), try!(divide(im, denominator))))
+}
+
+fn native_plus(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let mut sum = (Number::Exact(0), Number::Exact(0));
+    for arg in args.iter() {
+        sum = try!(complex_add("+", sum, try!(expect_complex("+", arg))));
+    }
+    Ok(complex_value(sum))
+}
+
+fn native_minus(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let nums: Vec<Complex> = try!(args.iter().map(|v| expect_complex("-", v)).collect());
+    match nums.split_first() {
+        Some((&first, &[])) => Ok(complex_value(try!(complex_subtract("-", (Number::Exact(0), Number::Exact(0)), first)))),
+        Some((&first, rest)) => {
+            let mut result = first;
+            for &n in rest.iter() {
+                result = try!(complex_subtract("-", result, n));
+            }
+            Ok(complex_value(result))
+        },
+        None => runtime_error!("Must supply at least one argument to -: {:?}", args)
+    }
+}
+
+fn native_multiply(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let mut product = (Number::Exact(1), Number::Exact(0));
+    for arg in args.iter() {
+        product = try!(complex_multiply("*", product, try!(expect_complex("*", arg))));
+    }
+    Ok(complex_value(product))
+}
+
+fn native_divide(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let nums: Vec<Complex> = try!(args.iter().map(|v| expect_complex("/", v)).collect());
+    match nums.split_first() {
+        Some((&first, &[])) => Ok(complex_value(try!(complex_divide((Number::Exact(1), Number::Exact(0)), first)))),
+        Some((&first, rest)) => {
+            let mut result = first;
+            for &n in rest.iter() {
+                result = try!(complex_divide(result, n));
+            }
+            Ok(complex_value(result))
+        },
+        None => runtime_error!("Must supply at least one argument to /: {:?}", args)
+    }
+}
+
+fn compare_numbers(a: Number, b: Number) -> Option<Ordering> {
+    match (a.fraction(), b.fraction()) {
+        // denominators are positive, so cross-multiplying keeps the order
+        (Some((n1, d1)), Some((n2, d2))) => Some((n1 * d2).cmp(&(n2 * d1))),
+        _ => a.to_f64().partial_cmp(&b.to_f64())
+    }
+}
+
+// Check that each adjacent pair of arguments is ordered the way test expects
+fn compare_chain(name: &str, args: &[Value], test: fn(Ordering) -> bool) -> Result<Value, RuntimeError> {
+    if args.is_empty() {
+        runtime_error!("Must supply at least one argument to {}: {:?}", name, args);
+    }
+    let nums = try!(expect_numbers(name, args));
+    let ordered = nums.windows(2).all(|pair| compare_numbers(pair[0], pair[1]).map_or(false, test));
+    Ok(Value::Boolean(ordered))
+}
+
+fn native_lessthan(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    compare_chain("<", args, |o| o == Ordering::Less)
+}
+
+fn native_greaterthan(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    compare_chain(">", args, |o| o == Ordering::Greater)
+}
+
+fn native_lessthan_or_equal(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    compare_chain("<=", args, |o| o != Ordering::Greater)
+}
+
+fn native_greaterthan_or_equal(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    compare_chain(">=", args, |o| o != Ordering::Less)
+}
+
+// Unlike the orderings, = also works on complex numbers
+fn native_equal(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.is_empty() {
+        runtime_error!("Must supply at least one argument to =: {:?}", args);
+    }
+    let nums: Vec<Complex> = try!(args.iter().map(|v| expect_complex("=", v)).collect());
+    let equal = nums.windows(2).all(|pair| {
+        compare_numbers(pair[0].0, pair[1].0) == Some(Ordering::Equal) && compare_numbers(pair[0].1, pair[1].1) == Some(Ordering::Equal)
+    });
+    Ok(Value::Boolean(equal))
+}
+
+// The quotient and remainder of integer division, rounding the quotient towards zero, or down when floor is set.
+// The quotient is None when it overflows, which only happens for i64::MIN divided by -1.
+fn integer_divide(name: &str, args: &[Value], floor: bool) -> Result<(Option<Number>, Number), RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to {}: {:?}", name, args);
+    }
+    let a = try!(expect_integer(name, &args[0]));
+    let b = try!(expect_integer(name, &args[1]));
+    if b.to_f64() == 0.0 {
+        runtime_error!("Division by zero in {}", name);
+    }
+    match (a, b) {
+        (Number::Exact(x), Number::Exact(y)) => {
+            // the remainder of i64::MIN by -1 is still 0, even though the quotient doesn't fit
+            let mut q = x.checked_div(y);
+            let mut r = x.checked_rem(y).unwrap_or(0);
+            if floor && r != 0 && (r < 0) != (y < 0) {
+                q = q.map(|q| q - 1);
+                r += y;
+            }
+            Ok((q.map(Number::Exact), Number::Exact(r)))
+        },
+        _ => {
+            let (x, y) = (a.to_f64(), b.to_f64());
+            let q = if floor { (x / y).floor() } else { (x / y).trunc() };
+            Ok((Some(Number::Inexact(q)), Number::Inexact(x - y * q)))
+        }
+    }
+}
+
+fn expect_quotient(name: &str, args: &[Value], q: Option<Number>) -> Result<Number, RuntimeError> {
+    match q {
+        Some(q) => Ok(q),
+        None => runtime_error!("Integer overflow in {}: {:?} and {:?}", name, args[0], args[1])
+    }
+}
+
+fn native_quotient(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let (q, _) = try!(integer_divide("quotient", args, false));
+    Ok(try!(expect_quotient("quotient", args, q)).to_value())
+}
+
+fn native_remainder(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    Ok(try!(integer_divide("remainder", args, false)).1.to_value())
+}
+
+fn native_modulo(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    Ok(try!(integer_divide("modulo", args, true)).1.to_value())
+}
+
+// There are no multiple values, so floor/ and truncate/ return the quotient and remainder as a list
+fn native_floor_divide(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let (q, r) = try!(integer_divide("floor/", args, true));
+    let q = try!(expect_quotient("floor/", args, q));
+    Ok(Value::List(vec![q.to_value(), r.to_value()]))
+}
+
+fn native_truncate_divide(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let (q, r) = try!(integer_divide("truncate/", args, false));
+    let q = try!(expect_quotient("truncate/", args, q));
+    Ok(Value::List(vec![q.to_value(), r.to_value()]))
+}
+
+fn native_abs(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to abs: {:?}", args);
+    }
+    Ok(try!(number_abs("abs", try!(expect_number("abs", &args[0])))).to_value())
+}
+
+fn number_abs(name: &str, n: Number) -> Result<Number, RuntimeError> {
+    match n {
+        Number::Exact(i) => match i.checked_abs() {
+            Some(res) => Ok(Number::Exact(res)),
+            None => runtime_error!("Integer overflow in {}: {}", name, i)
+        },
+        Number::Rational(num, den) => match num.checked_abs() {
+            Some(res) => Ok(Number::Rational(res, den)),
+            None => runtime_error!("Integer overflow in {}: {}/{}", name, num, den)
+        },
+        Number::Inexact(f) => Ok(Number::Inexact(f.abs()))
+    }
+}
+
+// The result is inexact if any of the arguments are
+fn extremum(name: &str, args: &[Value], pick: Ordering) -> Result<Value, RuntimeError> {
+    let nums = try!(expect_numbers(name, args));
+    let mut best = match nums.first() {
+        Some(&n) => n,
+        None => runtime_error!("Must supply at least one argument to {}: {:?}", name, args)
+    };
+    for &n in nums.iter() {
+        if compare_numbers(n, best) == Some(pick) || n.to_f64().is_nan() {
+            best = n;
+        }
+    }
+    if nums.iter().all(|n| n.is_exact()) {
+        Ok(best.to_value())
+    } else {
+        Ok(Value::Float(best.to_f64()))
+    }
+}
+
+fn native_min(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    extremum("min", args, Ordering::Less)
+}
+
+fn native_max(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    extremum("max", args, Ordering::Greater)
+}
+
+// The sign is left as it falls, since the absolute value of i64::MIN doesn't fit
+fn gcd(a: i64, b: i64) -> i64 {
+    // the remainder only overflows for i64::MIN and -1, where it is really 0
+    if b == 0 { a } else { gcd(b, a.checked_rem(b).unwrap_or(0)) }
+}
+
+// Inexact integers can be far outside the range of i64, so they get their own gcd
+fn float_gcd(a: f64, b: f64) -> f64 {
+    if b == 0.0 { a.abs() } else { float_gcd(b, a % b) }
+}
+
+fn native_gcd(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let mut res = Number::Exact(0);
+    for arg in args.iter() {
+        let n = try!(expect_integer("gcd", arg));
+        res = try!(arithmetic("gcd", res, n, |x, y| Some(gcd(x, y)), float_gcd));
+    }
+    Ok(try!(number_abs("gcd", res)).to_value())
+}
+
+fn native_lcm(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let mut res = Number::Exact(1);
+    for arg in args.iter() {
+        let n = try!(expect_integer("lcm", arg));
+        res = try!(arithmetic("lcm", res, n, |x, y| {
+            if x == 0 || y == 0 { Some(0) } else { x.checked_div(gcd(x, y)).and_then(|q| q.checked_mul(y)).and_then(i64::checked_abs) }
+        }, |x, y| {
+            if x == 0.0 || y == 0.0 { 0.0 } else { (x / float_gcd(x, y) * y).abs() }
+        }));
+    }
+    Ok(res.to_value())
+}
+
+fn native_expt(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to expt: {:?}", args);
+    }
+    let (base, power) = match (try!(expect_complex("expt", &args[0])), try!(expect_complex("expt", &args[1]))) {
+        ((b, Number::Exact(0)), (p, Number::Exact(0))) if !(b.to_f64() < 0.0 && p.to_f64().is_finite() && p.to_f64().fract() != 0.0) => (b, p),
+        // whole powers of complex numbers are worked out by squaring, so exact numbers stay exact
+        (base, (Number::Exact(mut p), Number::Exact(0))) if p >= 0 => {
+            let (mut res, mut square) = ((Number::Exact(1), Number::Exact(0)), base);
+            while p > 0 {
+                if p % 2 == 1 {
+                    res = try!(complex_multiply("expt", res, square));
+                }
+                p /= 2;
+                if p > 0 {
+                    square = try!(complex_multiply("expt", square, square));
+                }
+            }
+            return Ok(complex_value(res));
+        },
+        // other complex arguments, and fractional powers of negative numbers, have complex results
+        (base, power) => return Ok(complex_value(complex_exp(try!(complex_multiply("expt", power, complex_log(base))))))
+    };
+    match (base, power) {
+        (Number::Exact(b), Number::Exact(p)) if p >= 0 => {
+            let res = if p > u32::MAX as i64 { None } else { b.checked_pow(p as u32) };
+            match res {
+                Some(res) => Ok(Value::Integer(res)),
+                None if b == 0 || b == 1 => Ok(Value::Integer(b)),
+                None if b == -1 => Ok(Value::Integer(if p % 2 == 0 { 1 } else { -1 })),
+                None => runtime_error!("Integer overflow in expt: {} and {}", b, p)
+            }
+        },
+        (Number::Exact(0), Number::Exact(_)) => runtime_error!("Division by zero in expt"),
+        (Number::Exact(b), Number::Exact(p)) if b == 1 || b == -1 => Ok(Value::Integer(if p % 2 == 0 { 1 } else { b })),
+        // exact fractions, and negative powers, which are the reciprocal of the positive power
+        (_, Number::Exact(p)) if base.is_exact() => {
+            let (mut n, mut res, mut square) = (p.unsigned_abs(), Number::Exact(1), base);
+            while n > 0 {
+                if n % 2 == 1 {
+                    res = try!(multiply("expt", res, square));
+                }
+                n /= 2;
+                if n > 0 {
+                    square = try!(multiply("expt", square, square));
+                }
+            }
+            if p < 0 {
+                res = try!(divide(Number::Exact(1), res));
+            }
+            Ok(res.to_value())
+        },
+        _ => Ok(Value::Float(base.to_f64().powf(power.to_f64())))
+    }
+}
+
+// The largest integer whose square is no more than n
+fn isqrt(n: i64) -> i64 {
+    let mut s = (n as f64).sqrt() as i64;
+    while s * s > n {
+        s -= 1;
+    }
+    while (s + 1).checked_mul(s + 1).map_or(false, |sq| sq <= n) {
+        s += 1;
+    }
+    s
+}
+
+fn native_exact_integer_sqrt(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to exact-integer-sqrt: {:?}", args);
+    }
+    match args[0] {
+        Value::Integer(n) if n >= 0 => {
+            // returned as a list, for want of multiple values
+            let s = isqrt(n);
+            Ok(Value::List(vec![Value::Integer(s), Value::Integer(n - s * s)]))
+        },
+        _ => runtime_error!("Must supply a non-negative exact integer to exact-integer-sqrt: {:?}", args[0])
+    }
+}
+
+fn number_predicate(name: &str, args: &[Value], test: fn(f64) -> bool) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to {}: {:?}", name, args);
+    }
+    let n = try!(expect_number(name, &args[0]));
+    Ok(Value::Boolean(test(n.to_f64())))
+}
+
+fn native_is_zero(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let (re, im) = try!(expect_one_complex("zero?", args));
+    Ok(Value::Boolean(re.to_f64() == 0.0 && im.to_f64() == 0.0))
+}
+
+fn native_is_positive(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    number_predicate("positive?", args, |x| x > 0.0)
+}
+
+fn native_is_negative(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    number_predicate("negative?", args, |x| x < 0.0)
+}
+
+fn native_is_odd(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to odd?: {:?}", args);
+    }
+    match try!(expect_integer("odd?", &args[0])) {
+        Number::Exact(i) => Ok(Value::Boolean(i % 2 != 0)),
+        n => Ok(Value::Boolean(n.to_f64() % 2.0 != 0.0))
+    }
+}
+
+fn native_is_even(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to even?: {:?}", args);
+    }
+    match try!(expect_integer("even?", &args[0])) {
+        Number::Exact(i) => Ok(Value::Boolean(i % 2 == 0)),
+        n => Ok(Value::Boolean(n.to_f64() % 2.0 == 0.0))
+    }
+}
+
+fn native_is_number(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to number?: {:?}", args);
+    }
+    Ok(Value::Boolean(expect_complex("number?", &args[0]).is_ok()))
+}
+
+fn native_is_complex(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to complex?: {:?}", args);
+    }
+    Ok(Value::Boolean(expect_complex("complex?", &args[0]).is_ok()))
+}
+
+fn native_is_real(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to real?: {:?}", args);
+    }
+    Ok(Value::Boolean(expect_number("real?", &args[0]).is_ok()))
+}
+
+// Every finite real is a rational number, even a float
+fn native_is_rational(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to rational?: {:?}", args);
+    }
+    match expect_number("rational?", &args[0]) {
+        Ok(n) => Ok(Value::Boolean(n.to_f64().is_finite())),
+        Err(_) => Ok(Value::Boolean(false))
+    }
+}
+
+// The numerator or denominator of a number in lowest terms; floats are taken at their exact value
+fn fraction_part(name: &str, args: &[Value], numerator: bool) -> Result<Value, RuntimeError> {
+    let n = try!(expect_one_number(name, args));
+    let (num, den) = match try!(to_exact(n)).fraction() {
+        Some(fraction) => fraction,
+        None => runtime_error!("Must supply a rational number to {}: {:?}", name, args[0])
+    };
+    let part = Number::Exact((if numerator { num } else { den }) as i64);
+    if n.is_exact() { Ok(part.to_value()) } else { Ok(Value::Float(part.to_f64())) }
+}
+
+fn native_numerator(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    fraction_part("numerator", args, true)
+}
+
+fn native_denominator(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    fraction_part("denominator", args, false)
+}
+
+fn native_is_integer(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to integer?: {:?}", args);
+    }
+    Ok(Value::Boolean(expect_integer("integer?", &args[0]).is_ok()))
+}
+
+fn expect_one_number(name: &str, args: &[Value]) -> Result<Number, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to {}: {:?}", name, args);
+    }
+    expect_number(name, &args[0])
+}
+
+fn expect_one_complex(name: &str, args: &[Value]) -> Result<Complex, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to {}: {:?}", name, args);
+    }
+    expect_complex(name, &args[0])
+}
+
+// Transcendental functions always give an inexact result. Real arguments in the domain of the float function
+// use it directly, and anything else goes through the complex version
+fn transcendental(name: &str, args: &[Value], real: fn(f64) -> f64, domain: fn(f64) -> bool,
+                  complex: fn(Complex) -> Result<Complex, RuntimeError>) -> Result<Value, RuntimeError> {
+    match try!(expect_one_complex(name, args)) {
+        (re, Number::Exact(0)) if domain(re.to_f64()) || re.to_f64().is_nan() => Ok(Value::Float(real(re.to_f64()))),
+        z => Ok(complex_value(try!(complex(z))))
+    }
+}
+
+fn native_sqrt(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    match try!(expect_one_complex("sqrt", args)) {
+        // exact perfect squares have exact roots, and so do their negations
+        (Number::Exact(n), Number::Exact(0)) if n >= 0 && isqrt(n) * isqrt(n) == n => Ok(Value::Integer(isqrt(n))),
+        (Number::Exact(n), Number::Exact(0)) if n < 0 && n > i64::MIN && isqrt(-n) * isqrt(-n) == -n => {
+            Ok(Value::Complex(Number::Exact(0), Number::Exact(isqrt(-n))))
+        },
+        // and so do fractions of perfect squares
+        (Number::Rational(n, d), Number::Exact(0)) if n > 0 && isqrt(n) * isqrt(n) == n && isqrt(d) * isqrt(d) == d => {
+            Ok(Value::Rational(isqrt(n), isqrt(d)))
+        },
+        (Number::Rational(n, d), Number::Exact(0)) if n < 0 && n > i64::MIN && isqrt(-n) * isqrt(-n) == -n && isqrt(d) * isqrt(d) == d => {
+            Ok(Value::Complex(Number::Exact(0), Number::Rational(isqrt(-n), isqrt(d))))
+        },
+        z => Ok(complex_value(complex_sqrt(z)))
+    }
+}
+
+// The principal square root, which has a non-negative real part
+fn complex_sqrt((re, im): Complex) -> Complex {
+    match im {
+        Number::Exact(0) if re.to_f64() >= 0.0 || re.to_f64().is_nan() => (Number::Inexact(re.to_f64().sqrt()), Number::Exact(0)),
+        Number::Exact(0) => (Number::Exact(0), Number::Inexact((-re.to_f64()).sqrt())),
+        _ => {
+            let (x, y) = (re.to_f64(), im.to_f64());
+            let m = x.hypot(y);
+            let root_im = ((m - x) / 2.0).sqrt();
+            (Number::Inexact(((m + x) / 2.0).sqrt()), Number::Inexact(if y < 0.0 { -root_im } else { root_im }))
+        }
+    }
+}
+
+fn complex_exp((re, im): Complex) -> Complex {
+    match im {
+        Number::Exact(0) => (Number::Inexact(re.to_f64().exp()), Number::Exact(0)),
+        _ => {
+            let (m, a) = (re.to_f64().exp(), im.to_f64());
+            (Number::Inexact(m * a.cos()), Number::Inexact(m * a.sin()))
+        }
+    }
+}
+
+fn native_exp(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    Ok(complex_value(complex_exp(try!(expect_one_complex("exp", args)))))
+}
+
+// The principal logarithm, which is complex for negative numbers
+fn complex_log((re, im): Complex) -> Complex {
+    match im {
+        Number::Exact(0) if re.to_f64() >= 0.0 || re.to_f64().is_nan() => (Number::Inexact(re.to_f64().ln()), Number::Exact(0)),
+        _ => {
+            let (x, y) = (re.to_f64(), im.to_f64());
+            (Number::Inexact(x.hypot(y).ln()), Number::Inexact(y.atan2(x)))
+        }
+    }
+}
+
+fn native_log(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    match args.len() {
+        1 => Ok(complex_value(complex_log(try!(expect_complex("log", &args[0]))))),
+        2 => {
+            let z = complex_log(try!(expect_complex("log", &args[0])));
+            let base = complex_log(try!(expect_complex("log", &args[1])));
+            Ok(complex_value(try!(complex_divide(z, base))))
+        },
+        _ => runtime_error!("Must supply one or two arguments to log: {:?}", args)
+    }
+}
+
+const COMPLEX_I: Complex = (Number::Exact(0), Number::Exact(1));
+
+fn complex_negate(name: &str, z: Complex) -> Result<Complex, RuntimeError> {
+    complex_subtract(name, (Number::Exact(0), Number::Exact(0)), z)
+}
+
+// sin z = (e^iz - e^-iz) / 2i
+fn complex_sin(z: Complex) -> Result<Complex, RuntimeError> {
+    let iz = try!(complex_multiply("sin", COMPLEX_I, z));
+    let difference = try!(complex_subtract("sin", complex_exp(iz), complex_exp(try!(complex_negate("sin", iz)))));
+    complex_divide(difference, (Number::Exact(0), Number::Exact(2)))
+}
+
+// cos z = (e^iz + e^-iz) / 2
+fn complex_cos(z: Complex) -> Result<Complex, RuntimeError> {
+    let iz = try!(complex_multiply("cos", COMPLEX_I, z));
+    let sum = try!(complex_add("cos", complex_exp(iz), complex_exp(try!(complex_negate("cos", iz)))));
+    complex_divide(sum, (Number::Exact(2), Number::Exact(0)))
+}
+
+fn complex_tan(z: Complex) -> Result<Complex, RuntimeError> {
+    complex_divide(try!(complex_sin(z)), try!(complex_cos(z)))
+}
+
+// asin z = -i log(iz + sqrt(1 - z^2))
+fn complex_asin(z: Complex) -> Result<Complex, RuntimeError> {
+    let root = complex_sqrt(try!(complex_subtract("asin", (Number::Exact(1), Number::Exact(0)), try!(complex_multiply("asin", z, z)))));
+    let log = complex_log(try!(complex_add("asin", try!(complex_multiply("asin", COMPLEX_I, z)), root)));
+    complex_multiply("asin", (Number::Exact(0), Number::Exact(-1)), log)
+}
+
+// acos z = pi/2 - asin z
+fn complex_acos(z: Complex) -> Result<Complex, RuntimeError> {
+    complex_subtract("acos", (Number::Inexact(::std::f64::consts::FRAC_PI_2), Number::Exact(0)), try!(complex_asin(z)))
+}
+
+// atan z = (log(1 + iz) - log(1 - iz)) / 2i
+fn complex_atan(z: Complex) -> Result<Complex, RuntimeError> {
+    let iz = try!(complex_multiply("atan", COMPLEX_I, z));
+    let one = (Number::Exact(1), Number::Exact(0));
+    let difference = try!(complex_subtract("atan", complex_log(try!(complex_add("atan", one, iz))), complex_log(try!(complex_subtract("atan", one, iz)))));
+    complex_divide(difference, (Number::Exact(0), Number::Exact(2)))
+}
+
+fn native_sin(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    transcendental("sin", args, f64::sin, |_| true, complex_sin)
+}
+
+fn native_cos(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    transcendental("cos", args, f64::cos, |_| true, complex_cos)
+}
+
+fn native_tan(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    transcendental("tan", args, f64::tan, |_| true, complex_tan)
+}
+
+// outside [-1, 1] the inverse sine and cosine are complex
+fn native_asin(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    transcendental("asin", args, f64::asin, |x| x.abs() <= 1.0, complex_asin)
+}
+
+fn native_acos(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    transcendental("acos", args, f64::acos, |x| x.abs() <= 1.0, complex_acos)
+}
+
+fn native_atan(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    match args.len() {
+        1 => transcendental("atan", args, f64::atan, |_| true, complex_atan),
+        2 => {
+            let y = try!(expect_number("atan", &args[0])).to_f64();
+            let x = try!(expect_number("atan", &args[1])).to_f64();
+            Ok(Value::Float(y.atan2(x)))
+        },
+        _ => runtime_error!("Must supply one or two arguments to atan: {:?}", args)
+    }
+}
+
+// Exact integers are already rounded. Floats need f applied, and exact fractions n/d (with d positive) need
+// fraction, which works in i128 so that negating i64::MIN can't overflow
+fn rounding_function(name: &str, args: &[Value], f: fn(f64) -> f64, fraction: fn(i128, i128) -> i128) -> Result<Value, RuntimeError> {
+    match try!(expect_one_number(name, args)) {
+        Number::Exact(i) => Ok(Value::Integer(i)),
+        Number::Rational(n, d) => Ok(Value::Integer(fraction(n as i128, d as i128) as i64)),
+        Number::Inexact(x) => Ok(Value::Float(f(x)))
+    }
+}
+
+// Round to the nearest integer, with halfway cases going to the even neighbour
+fn round_half_even(x: f64) -> f64 {
+    if (x - x.trunc()).abs() == 0.5 {
+        2.0 * (x / 2.0).round()
+    } else {
+        x.round()
+    }
+}
+
+fn round_fraction_half_even(n: i128, d: i128) -> i128 {
+    let floor = n.div_euclid(d);
+    match (2 * n.rem_euclid(d)).cmp(&d) {
+        Ordering::Less => floor,
+        Ordering::Greater => floor + 1,
+        Ordering::Equal => if floor % 2 == 0 { floor } else { floor + 1 }
+    }
+}
+
+fn native_floor(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    rounding_function("floor", args, f64::floor, i128::div_euclid)
+}
+
+fn native_ceiling(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    rounding_function("ceiling", args, f64::ceil, |n, d| -(-n).div_euclid(d))
+}
+
+fn native_round(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    rounding_function("round", args, round_half_even, round_fraction_half_even)
+}
+
+fn native_truncate(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    rounding_function("truncate", args, f64::trunc, |n, d| n / d)
+}
+
+fn to_exact(n: Number) -> Result<Number, RuntimeError> {
+    match n {
+        Number::Inexact(f) if f.is_finite() => match Number::exact_from_f64(f) {
+            Some(res) => Ok(res),
+            None => runtime_error!("Integer overflow in exact: {:?}", Value::Float(f))
+        },
+        Number::Inexact(f) => runtime_error!("No exact equivalent for {:?}", Value::Float(f)),
+        n => Ok(n)
+    }
+}
+
+fn native_exact(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let (re, im) = try!(expect_one_complex("exact", args));
+    Ok(complex_value((try!(to_exact(re)), try!(to_exact(im)))))
+}
+
+fn native_inexact(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    match try!(expect_one_complex("inexact", args)) {
+        (re, Number::Exact(0)) => Ok(Value::Float(re.to_f64())),
+        (re, im) => Ok(Value::Complex(Number::Inexact(re.to_f64()), Number::Inexact(im.to_f64())))
+    }
+}
+
+fn native_square(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let n = try!(expect_one_complex("square", args));
+    Ok(complex_value(try!(complex_multiply("square", n, n))))
+}
+
+fn native_make_rectangular(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to make-rectangular: {:?}", args);
+    }
+    let re = try!(expect_number("make-rectangular", &args[0]));
+    let im = try!(expect_number("make-rectangular", &args[1]));
+    Ok(complex_value((re, im)))
+}
+
+fn native_make_polar(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to make-polar: {:?}", args);
+    }
+    let magnitude = try!(expect_number("make-polar", &args[0]));
+    match try!(expect_number("make-polar", &args[1])) {
+        Number::Exact(0) => Ok(magnitude.to_value()),
+        angle => {
+            let (m, a) = (magnitude.to_f64(), angle.to_f64());
+            Ok(Value::Complex(Number::Inexact(m * a.cos()), Number::Inexact(m * a.sin())))
+        }
+    }
+}
+
+fn native_real_part(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    Ok(try!(expect_one_complex("real-part", args)).0.to_value())
+}
+
+fn native_imag_part(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    Ok(try!(expect_one_complex("imag-part", args)).1.to_value())
+}
+
+fn native_magnitude(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    match try!(expect_one_complex("magnitude", args)) {
+        (re, Number::Exact(0)) => Ok(try!(number_abs("magnitude", re)).to_value()),
+        (Number::Exact(x), Number::Exact(y)) => {
+            // exact when the sum of the squares is a perfect square, like 3+4i
+            match x.checked_mul(x).and_then(|xx| y.checked_mul(y).and_then(|yy| xx.checked_add(yy))) {
+                Some(sum) if isqrt(sum) * isqrt(sum) == sum => Ok(Value::Integer(isqrt(sum))),
+                _ => Ok(Value::Float((x as f64).hypot(y as f64)))
+            }
+        },
+        (re, im) => Ok(Value::Float(re.to_f64().hypot(im.to_f64())))
+    }
+}
+
+fn native_angle(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    match try!(expect_one_complex("angle", args)) {
+        (re, Number::Exact(0)) if re.is_exact() && re.to_f64() >= 0.0 => Ok(Value::Integer(0)),
+        (re, im) => Ok(Value::Float(im.to_f64().atan2(re.to_f64())))
+    }
+}
+
+// There are no bignums, so the bitwise procedures work on the i64 two's complement representation
+fn expect_exact_integer(name: &str, v: &Value) -> Result<i64, RuntimeError> {
+    match *v {
+        Value::Integer(i) => Ok(i),
+        _ => runtime_error!("Must supply an exact integer to {}: {:?}", name, v)
+    }
+}
+
+fn expect_bit_index(name: &str, v: &Value) -> Result<u64, RuntimeError> {
+    match *v {
+        Value::Integer(i) if i >= 0 => Ok(i as u64),
+        _ => runtime_error!("Must supply a non-negative bit index to {}: {:?}", name, v)
+    }
+}
+
+fn bitwise_fold(name: &str, args: &[Value], identity: i64, f: fn(i64, i64) -> i64) -> Result<Value, RuntimeError> {
+    let mut res = identity;
+    for arg in args.iter() {
+        res = f(res, try!(expect_exact_integer(name, arg)));
+    }
+    Ok(Value::Integer(res))
+}
+
+fn native_bitwise_and(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    bitwise_fold("bitwise-and", args, -1, |x, y| x & y)
+}
+
+fn native_bitwise_or(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    bitwise_fold("bitwise-or", args, 0, |x, y| x | y)
+}
+
+fn native_bitwise_xor(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    bitwise_fold("bitwise-xor", args, 0, |x, y| x ^ y)
+}
+
+fn native_bitwise_not(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to bitwise-not: {:?}", args);
+    }
+    Ok(Value::Integer(!try!(expect_exact_integer("bitwise-not", &args[0]))))
+}
+
+fn native_arithmetic_shift(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to arithmetic-shift: {:?}", args);
+    }
+    let n = try!(expect_exact_integer("arithmetic-shift", &args[0]));
+    let count = try!(expect_exact_integer("arithmetic-shift", &args[1]));
+    if count < 0 {
+        // shifting right by 64 or more leaves only the sign
+        let count = if count < -63 { 63 } else { -count as u32 };
+        return Ok(Value::Integer(n >> count));
+    }
+    let shifted = if count > 63 { 0 } else { n << count };
+    if shifted >> count.min(63) != n {
+        runtime_error!("Integer overflow in arithmetic-shift: {} and {}", n, count);
+    }
+    Ok(Value::Integer(shifted))
+}
+
+fn native_bit_count(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to bit-count: {:?}", args);
+    }
+    // negative numbers count their zero bits instead
+    let n = try!(expect_exact_integer("bit-count", &args[0]));
+    let bits = if n < 0 { (!n).count_ones() } else { n.count_ones() };
+    Ok(Value::Integer(bits as i64))
+}
+
+fn native_integer_length(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to integer-length: {:?}", args);
+    }
+    let n = try!(expect_exact_integer("integer-length", &args[0]));
+    let bits = if n < 0 { !n } else { n };
+    Ok(Value::Integer(64 - bits.leading_zeros() as i64))
+}
+
+fn native_is_bit_set(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to bit-set?: {:?}", args);
+    }
+    // bits past the top all repeat the sign
+    let index = try!(expect_bit_index("bit-set?", &args[0])).min(63);
+    let n = try!(expect_exact_integer("bit-set?", &args[1]));
+    Ok(Value::Boolean((n >> index) & 1 == 1))
+}
+
+fn native_copy_bit(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 3 {
+        runtime_error!("Must supply exactly three arguments to copy-bit: {:?}", args);
+    }
+    let index = try!(expect_bit_index("copy-bit", &args[0]));
+    let n = try!(expect_exact_integer("copy-bit", &args[1]));
+    // bits past the top all repeat the sign, so they can only be copied if they already match
+    if index > 63 {
+        match args[2] {
+            Value::Boolean(b) if b == (n < 0) => return Ok(Value::Integer(n)),
+            Value::Boolean(_) => runtime_error!("Integer overflow in copy-bit: {} and {}", index, n),
+            _ => {}
+        }
+    }
+    match args[2] {
+        Value::Boolean(true) => Ok(Value::Integer(n | (1 << index))),
+        Value::Boolean(false) => Ok(Value::Integer(n & !(1 << index))),
+        _ => runtime_error!("Must supply a boolean to copy-bit: {:?}", args[2])
+    }
+}
+
+fn native_bit_field(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 3 {
+        runtime_error!("Must supply exactly three arguments to bit-field: {:?}", args);
+    }
+    let n = try!(expect_exact_integer("bit-field", &args[0]));
+    let start = try!(expect_bit_index("bit-field", &args[1]));
+    let end = try!(expect_bit_index("bit-field", &args[2]));
+    if end < start {
+        runtime_error!("Must supply a start no greater than the end to bit-field: {} and {}", start, end);
+    }
+    if end > 63 && n < 0 {
+        runtime_error!("Integer overflow in bit-field: {} from {} to {}", n, start, end);
+    }
+    let width = end.min(63) - start.min(63);
+    let mask = if width >= 63 { i64::MAX } else { (1 << width) - 1 };
+    Ok(Value::Integer((n >> start.min(63)) & mask))
+}
+
+fn native_and(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let mut res = Value::Boolean(true);
+    for n in args.iter() {
+        let v = try!(evaluate_value(n, env.clone()));
+        match v {
+            Value::Boolean(false) => return Ok(Value::Boolean(false)),
+            _ => res = v
+        }
+    }
+    Ok(res)
+}
+
+fn native_or(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    for n in args.iter() {
+        let v = try!(evaluate_value(n, env.clone()));
+        match v {
+            Value::Boolean(false) => (),
+            _ => return Ok(v)
+        }
+    }
+    Ok(Value::Boolean(false))
+}
+
+fn native_null(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to null?: {:?}", args);
+    }
+    let v = args[0].clone();
+    match v {
+        Value::List(l) => Ok(Value::Boolean(l.len() == 0)),
+        _ => Ok(Value::Boolean(false))
+    }
+}
+
+fn native_list(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    Ok(Value::List(args.to_vec()))
+}
+
+fn native_car(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to car: {:?}", args);
+    }
+    let v = args[0].clone();
+    match v {
+        Value::List(mut l) => {
+            if l.len() > 0 {
+                Ok(l.remove(0))
+            } else {
+                runtime_error!("Can't run car on an empty list")
+            }
+        }
+        _ => runtime_error!("Must supply a list to car")
+    }
+}
+
+fn native_cdr(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to cdr: {:?}", args);
+    }
+    let v = args[0].clone();
+    match v {
+        Value::List(mut l) => {
+            if l.len() > 0 {
+                l.remove(0);
+                Ok(Value::List(l))
+            } else {
+                runtime_error!("Can't run cdr on an empty list")
+            }
+        }
+        _ => runtime_error!("Must supply a list to cdr")
+    }
+}
+
+fn native_cons(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to cons: {:?}", args);
+    }
+
+    let first = args[0].clone();
+    let second = args[1].clone();
+    match second {
+        Value::List(elements) => {
+            let mut new_elements = vec![first];
+            for e in elements.into_iter() {
+                new_elements.push(e);
+            }
+            return Ok(Value::List(new_elements))
+        }
+        _ => runtime_error!("Second argument to cons must be a list: {:?}", second)
+    }
+}
+
+fn native_append(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let mut elements = Vec::new();
+    for arg in args.iter() {
+        match *arg {
+            Value::List(ref l) => elements.extend(l.iter().cloned()),
+            ref v => runtime_error!("Arguments to append must be lists: {:?}", v)
+        }
+    }
+    Ok(Value::List(elements))
+}
+
+// Lists are vectors of values without any identity of their own, so eq? and eqv? compare them by contents
+// just like equal? does.  Vectors are mutable and shared, so they are compared by identity.
+fn values_eqv(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (&Value::Vector(ref v1), &Value::Vector(ref v2)) => Rc::ptr_eq(v1, v2),
+        (&Value::Bytevector(ref b1), &Value::Bytevector(ref b2)) => Rc::ptr_eq(b1, b2),
+        _ => a == b
+    }
+}
+
+fn is_true(v: &Value) -> bool {
+    match *v {
+        Value::Boolean(false) => false,
+        _ => true
+    }
+}
+
+fn expect_list(name: &str, v: Value) -> Result<Vec<Value>, RuntimeError> {
+    match v {
+        Value::List(l) => Ok(l),
+        _ => runtime_error!("Must supply a list to {}: {:?}", name, v)
+    }
+}
+
+fn expect_procedure(name: &str, v: Value) -> Result<Function, RuntimeError> {
+    match v {
+        Value::Procedure(f) => Ok(f),
+        _ => runtime_error!("Must supply a procedure to {}: {:?}", name, v)
+    }
+}
+
+fn expect_index(name: &str, v: Value) -> Result<usize, RuntimeError> {
+    match v {
+        Value::Integer(i) if i >= 0 => Ok(i as usize),
+        _ => runtime_error!("Must supply a non-negative integer to {}: {:?}", name, v)
+    }
+}
+
+// Unpack a procedure argument followed by one or more list arguments
+fn procedure_and_lists(name: &str, args: &[Value]) -> Result<(Function, Vec<Vec<Value>>), RuntimeError> {
+    if args.len() < 2 {
+        runtime_error!("Must supply at least two arguments to {}: {:?}", name, args);
+    }
+    let mut vals = args.to_vec();
+    let func = try!(expect_procedure(name, vals.remove(0)));
+    let res: Result<Vec<Vec<Value>>, RuntimeError> = vals.into_iter().map(|v| expect_list(name, v)).collect();
+    Ok((func, try!(res)))
+}
+
+// Gather the nth element of each list, for procedures that walk several lists in parallel
+fn nth_elements(lists: &[Vec<Value>], n: usize) -> Vec<Value> {
+    lists.iter().map(|l| l[n].clone()).collect()
+}
+
+fn shortest_length(lists: &[Vec<Value>]) -> usize {
+    lists.iter().map(|l| l.len()).min().unwrap_or(0)
+}
+
+fn native_length(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to length: {:?}", args);
+    }
+    let list = try!(expect_list("length", args[0].clone()));
+    Ok(Value::Integer(list.len() as i64))
+}
+
+fn native_reverse(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to reverse: {:?}", args);
+    }
+    let mut list = try!(expect_list("reverse", args[0].clone()));
+    list.reverse();
+    Ok(Value::List(list))
+}
+
+fn native_list_ref(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to list-ref: {:?}", args);
+    }
+    let list = try!(expect_list("list-ref", args[0].clone()));
+    let k = try!(expect_index("list-ref", args[1].clone()));
+    match list.into_iter().nth(k) {
+        Some(v) => Ok(v),
+        None => runtime_error!("Index out of range in list-ref: {}", k)
+    }
+}
+
+fn native_list_tail(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to list-tail: {:?}", args);
+    }
+    let list = try!(expect_list("list-tail", args[0].clone()));
+    let k = try!(expect_index("list-tail", args[1].clone()));
+    if k > list.len() {
+        runtime_error!("Index out of range in list-tail: {}", k);
+    }
+    Ok(Value::List(list.into_iter().skip(k).collect()))
+}
+
+fn native_last(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to last: {:?}", args);
+    }
+    let mut list = try!(expect_list("last", args[0].clone()));
+    match list.pop() {
+        Some(v) => Ok(v),
+        None => runtime_error!("Can't run last on an empty list")
+    }
+}
+
+fn native_iota(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() < 1 || args.len() > 3 {
+        runtime_error!("Must supply one to three arguments to iota: {:?}", args);
+    }
+    let vals = args.to_vec();
+    let count = try!(expect_index("iota", vals[0].clone()));
+    let mut nums = Vec::new();
+    for v in (&vals[1..]).iter() {
+        match *v {
+            Value::Integer(i) => nums.push(i),
+            _ => runtime_error!("Must supply integers to iota: {:?}", v)
+        }
+    }
+    let start = if nums.len() > 0 { nums[0] } else { 0 };
+    let step = if nums.len() > 1 { nums[1] } else { 1 };
+    let mut res = Vec::new();
+    for i in 0..count as i64 {
+        match i.checked_mul(step).and_then(|offset| start.checked_add(offset)) {
+            Some(n) => res.push(Value::Integer(n)),
+            None => runtime_error!("Integer overflow in iota")
+        }
+    }
+    Ok(Value::List(res))
+}
+
+fn native_map(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let (func, lists) = try!(procedure_and_lists("map", args));
+    let mut res = Vec::new();
+    for n in 0..shortest_length(&lists) {
+        res.push(try!(apply_function(&func, nth_elements(&lists, n), env.clone())));
+    }
+    Ok(Value::List(res))
+}
+
+fn native_for_each(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let (func, lists) = try!(procedure_and_lists("for-each", args));
+    for n in 0..shortest_length(&lists) {
+        try!(apply_function(&func, nth_elements(&lists, n), env.clone()));
+    }
+    Ok(Value::null())
+}
+
+// Split a list into the elements that do and don't satisfy a predicate
+fn partition_list(name: &str, args: &[Value], env: Rc<RefCell<Environment>>) -> Result<(Vec<Value>, Vec<Value>), RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to {}: {:?}", name, args);
+    }
+    let (func, mut lists) = try!(procedure_and_lists(name, args));
+    let mut matching = Vec::new();
+    let mut rest = Vec::new();
+    for v in lists.remove(0).into_iter() {
+        if is_true(&try!(apply_function(&func, vec![v.clone()], env.clone()))) {
+            matching.push(v);
+        } else {
+            rest.push(v);
+        }
+    }
+    Ok((matching, rest))
+}
+
+fn native_filter(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let (matching, _) = try!(partition_list("filter", args, env));
+    Ok(Value::List(matching))
+}
+
+fn native_remove(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let (_, rest) = try!(partition_list("remove", args, env));
+    Ok(Value::List(rest))
+}
+
+// There are no multiple return values, so partition returns a list of the two lists instead
+fn native_partition(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let (matching, rest) = try!(partition_list("partition", args, env));
+    Ok(Value::List(vec![Value::List(matching), Value::List(rest)]))
+}
+
+// Unpack the arguments to fold and fold-right: (<proc> <initial value> <list> ...)
+fn fold_args(name: &str, args: &[Value]) -> Result<(Function, Value, Vec<Vec<Value>>), RuntimeError> {
+    if args.len() < 3 {
+        runtime_error!("Must supply at least three arguments to {}: {:?}", name, args);
+    }
+    let mut vals = args.to_vec();
+    let func = try!(expect_procedure(name, vals.remove(0)));
+    let init = vals.remove(0);
+    let res: Result<Vec<Vec<Value>>, RuntimeError> = vals.into_iter().map(|v| expect_list(name, v)).collect();
+    Ok((func, init, try!(res)))
+}
+
+fn native_fold(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let (func, mut acc, lists) = try!(fold_args("fold", args));
+    for n in 0..shortest_length(&lists) {
+        let mut call_args = nth_elements(&lists, n);
+        call_args.push(acc);
+        acc = try!(apply_function(&func, call_args, env.clone()));
+    }
+    Ok(acc)
+}
+
+fn native_fold_right(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let (func, mut acc, lists) = try!(fold_args("fold-right", args));
+    for n in (0..shortest_length(&lists)).rev() {
+        let mut call_args = nth_elements(&lists, n);
+        call_args.push(acc);
+        acc = try!(apply_function(&func, call_args, env.clone()));
+    }
+    Ok(acc)
+}
+
+fn native_reduce(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 3 {
+        runtime_error!("Must supply exactly three arguments to reduce: {:?}", args);
+    }
+    let (func, identity, mut lists) = try!(fold_args("reduce", args));
+    let mut list = lists.remove(0).into_iter();
+    let mut acc = match list.next() {
+        Some(v) => v,
+        None => return Ok(identity)
+    };
+    for v in list {
+        acc = try!(apply_function(&func, vec![v, acc], env.clone()));
+    }
+    Ok(acc)
+}
+
+fn native_any(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let (func, lists) = try!(procedure_and_lists("any", args));
+    for n in 0..shortest_length(&lists) {
+        let res = try!(apply_function(&func, nth_elements(&lists, n), env.clone()));
+        if is_true(&res) {
+            return Ok(res);
+        }
+    }
+    Ok(Value::Boolean(false))
+}
+
+fn native_every(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let (func, lists) = try!(procedure_and_lists("every", args));
+    let mut res = Value::Boolean(true);
+    for n in 0..shortest_length(&lists) {
+        res = try!(apply_function(&func, nth_elements(&lists, n), env.clone()));
+        if !is_true(&res) {
+            return Ok(res);
+        }
+    }
+    Ok(res)
+}
+
+fn native_find(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to find: {:?}", args);
+    }
+    let (func, mut lists) = try!(procedure_and_lists("find", args));
+    for v in lists.remove(0).into_iter() {
+        if is_true(&try!(apply_function(&func, vec![v.clone()], env.clone()))) {
+            return Ok(v);
+        }
+    }
+    Ok(Value::Boolean(false))
+}
+
+// Compare two values with an optional Scheme equality procedure, falling back to equal?
+fn compare_values(func: &Option<Function>, a: &Value, b: &Value, env: Rc<RefCell<Environment>>) -> Result<bool, RuntimeError> {
+    match *func {
+        Some(ref f) => Ok(is_true(&try!(apply_function(f, vec![a.clone(), b.clone()], env)))),
+        None => Ok(a == b)
+    }
+}
+
+// Unpack the arguments to procedures of the form (<name> <x> <list> [<compare>])
+fn search_args(name: &str, args: &[Value]) -> Result<(Value, Vec<Value>, Option<Function>), RuntimeError> {
+    if args.len() < 2 || args.len() > 3 {
+        runtime_error!("Must supply two or three arguments to {}: {:?}", name, args);
+    }
+    let mut vals = args.to_vec();
+    let compare = if vals.len() == 3 {
+        Some(try!(expect_procedure(name, vals.pop().unwrap())))
+    } else {
+        None
+    };
+    let list = try!(expect_list(name, vals.pop().unwrap()));
+    Ok((vals.pop().unwrap(), list, compare))
+}
+
+fn native_delete(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let (x, list, compare) = try!(search_args("delete", args));
+    let mut res = Vec::new();
+    for v in list.into_iter() {
+        if !try!(compare_values(&compare, &x, &v, env.clone())) {
+            res.push(v);
+        }
+    }
+    Ok(Value::List(res))
+}
+
+fn native_delete_duplicates(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() < 1 || args.len() > 2 {
+        runtime_error!("Must supply one or two arguments to delete-duplicates: {:?}", args);
+    }
+    let mut vals = args.to_vec();
+    let compare = if vals.len() == 2 {
+        Some(try!(expect_procedure("delete-duplicates", vals.pop().unwrap())))
+    } else {
+        None
+    };
+    let list = try!(expect_list("delete-duplicates", vals.pop().unwrap()));
+    let mut res: Vec<Value> = Vec::new();
+    for v in list.into_iter() {
+        let mut duplicate = false;
+        for seen in res.iter() {
+            if try!(compare_values(&compare, seen, &v, env.clone())) {
+                duplicate = true;
+                break;
+            }
+        }
+        if !duplicate {
+            res.push(v);
+        }
+    }
+    Ok(Value::List(res))
+}
+
+fn expect_vector(name: &str, v: Value) -> Result<Rc<RefCell<Vec<Value>>>, RuntimeError> {
+    match v {
+        Value::Vector(vec) => Ok(vec),
+        _ => runtime_error!("Must supply a vector to {}: {:?}", name, v)
+    }
+}
+
+fn native_vector(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let elements = args.to_vec();
+    Ok(Value::Vector(Rc::new(RefCell::new(elements))))
+}
+
+// A failed allocation aborts the whole process, so make-vector and make-bytevector refuse lengths past this
+const MAX_VECTOR_LENGTH: usize = 1 << 24;
+
+fn native_make_vector(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() < 1 || args.len() > 2 {
+        runtime_error!("Must supply one or two arguments to make-vector: {:?}", args);
+    }
+    let mut vals = args.to_vec();
+    let fill = if vals.len() == 2 { vals.pop().unwrap() } else { Value::Boolean(false) };
+    let k = try!(expect_index("make-vector", vals.pop().unwrap()));
+    if k > MAX_VECTOR_LENGTH {
+        runtime_error!("Length {} is too large for make-vector", k);
+    }
+    Ok(Value::Vector(Rc::new(RefCell::new(vec![fill; k]))))
+}
+
+fn native_is_vector(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to vector?: {:?}", args);
+    }
+    match args[0].clone() {
+        Value::Vector(_) => Ok(Value::Boolean(true)),
+        _ => Ok(Value::Boolean(false))
+    }
+}
+
+fn native_vector_length(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to vector-length: {:?}", args);
+    }
+    let vec = try!(expect_vector("vector-length", args[0].clone()));
+    let len = vec.borrow().len();
+    Ok(Value::Integer(len as i64))
+}
+
+fn native_vector_ref(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to vector-ref: {:?}", args);
+    }
+    let vec = try!(expect_vector("vector-ref", args[0].clone()));
+    let k = try!(expect_index("vector-ref", args[1].clone()));
+    let res = vec.borrow().get(k).cloned();
+    match res {
+        Some(v) => Ok(v),
+        None => runtime_error!("Index out of range in vector-ref: {}", k)
+    }
+}
+
+fn native_vector_set(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 3 {
+        runtime_error!("Must supply exactly three arguments to vector-set!: {:?}", args);
+    }
+    let vec = try!(expect_vector("vector-set!", args[0].clone()));
+    let k = try!(expect_index("vector-set!", args[1].clone()));
+    let val = args[2].clone();
+    let mut elements = vec.borrow_mut();
+    if k >= elements.len() {
+        runtime_error!("Index out of range in vector-set!: {}", k);
+    }
+    elements[k] = val;
+    Ok(Value::null())
+}
+
+fn native_vector_to_list(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to vector->list: {:?}", args);
+    }
+    let vec = try!(expect_vector("vector->list", args[0].clone()));
+    let elements = vec.borrow().clone();
+    Ok(Value::List(elements))
+}
+
+fn native_list_to_vector(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to list->vector: {:?}", args);
+    }
+    let list = try!(expect_list("list->vector", args[0].clone()));
+    Ok(Value::Vector(Rc::new(RefCell::new(list))))
+}
+
+// Stable merge of two sorted sequences: elements of the left sequence come first unless the right one is strictly less
+fn merge_values(left: Vec<Value>, right: Vec<Value>, less: &Function, env: Rc<RefCell<Environment>>) -> Result<Vec<Value>, RuntimeError> {
+    let mut res = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    loop {
+        let take_right = match (left.peek(), right.peek()) {
+            (Some(l), Some(r)) => is_true(&try!(apply_function(less, vec![r.clone(), l.clone()], env.clone()))),
+            (Some(_), None) => false,
+            (None, Some(_)) => true,
+            (None, None) => break
+        };
+        if take_right {
+            res.push(right.next().unwrap());
+        } else {
+            res.push(left.next().unwrap());
+        }
+    }
+    Ok(res)
+}
+
+// Stable merge sort, so that errors raised by the comparison procedure can be propagated
+fn sort_values(mut vals: Vec<Value>, less: &Function, env: Rc<RefCell<Environment>>) -> Result<Vec<Value>, RuntimeError> {
+    if vals.len() <= 1 {
+        return Ok(vals);
+    }
+    let right = vals.split_off(vals.len() / 2);
+    let left = try!(sort_values(vals, less, env.clone()));
+    let right = try!(sort_values(right, less, env.clone()));
+    merge_values(left, right, less, env)
+}
+
+// Sort a list or vector, either returning a new sequence or sorting a vector in place
+fn sort_sequence(name: &str, seq: Value, less: Value, in_place: bool, env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let less = try!(expect_procedure(name, less));
+    match seq {
+        Value::List(list) => Ok(Value::List(try!(sort_values(list, &less, env)))),
+        Value::Vector(vec) => {
+            let elements = vec.borrow().clone();
+            let sorted = try!(sort_values(elements, &less, env));
+            if in_place {
+                *vec.borrow_mut() = sorted;
+                Ok(Value::Vector(vec))
+            } else {
+                Ok(Value::Vector(Rc::new(RefCell::new(sorted))))
+            }
+        },
+        _ => runtime_error!("Must supply a list or vector to {}: {:?}", name, seq)
+    }
+}
+
+fn native_sort(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to sort: {:?}", args);
+    }
+    let mut vals = args.to_vec();
+    let less = vals.pop().unwrap();
+    sort_sequence("sort", vals.pop().unwrap(), less, false, env)
+}
+
+// Lists have no identity to mutate, so sort! returns the sorted list; vectors are sorted in place
+fn native_sort_in_place(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to sort!: {:?}", args);
+    }
+    let mut vals = args.to_vec();
+    let less = vals.pop().unwrap();
+    sort_sequence("sort!", vals.pop().unwrap(), less, true, env)
+}
+
+fn native_list_sort(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to list-sort: {:?}", args);
+    }
+    let mut vals = args.to_vec();
+    let list = try!(expect_list("list-sort", vals.pop().unwrap()));
+    sort_sequence("list-sort", Value::List(list), vals.pop().unwrap(), false, env)
+}
+
+fn native_vector_sort(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to vector-sort: {:?}", args);
+    }
+    let mut vals = args.to_vec();
+    let vec = try!(expect_vector("vector-sort", vals.pop().unwrap()));
+    sort_sequence("vector-sort", Value::Vector(vec), vals.pop().unwrap(), false, env)
+}
+
+fn native_merge(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 3 {
+        runtime_error!("Must supply exactly three arguments to merge: {:?}", args);
+    }
+    let mut vals = args.to_vec();
+    let less = try!(expect_procedure("merge", vals.pop().unwrap()));
+    let right = try!(expect_list("merge", vals.pop().unwrap()));
+    let left = try!(expect_list("merge", vals.pop().unwrap()));
+    Ok(Value::List(try!(merge_values(left, right, &less, env))))
+}
+
+// (vector-binary-search <vector> <value> <compare>), where compare returns a negative, zero or positive integer
+fn native_vector_binary_search(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 3 {
+        runtime_error!("Must supply exactly three arguments to vector-binary-search: {:?}", args);
+    }
+    let mut vals = args.to_vec();
+    let compare = try!(expect_procedure("vector-binary-search", vals.pop().unwrap()));
+    let value = vals.pop().unwrap();
+    let vec = try!(expect_vector("vector-binary-search", vals.pop().unwrap()));
+    let elements = vec.borrow().clone();
+    let (mut low, mut high) = (0, elements.len());
+    while low < high {
+        let mid = low + (high - low) / 2;
+        match try!(apply_function(&compare, vec![elements[mid].clone(), value.clone()], env.clone())) {
+            Value::Integer(0) => return Ok(Value::Integer(mid as i64)),
+            Value::Integer(i) if i < 0 => low = mid + 1,
+            Value::Integer(_) => high = mid,
+            v => runtime_error!("Comparison procedure in vector-binary-search must return an integer: {:?}", v)
+        }
+    }
+    Ok(Value::Boolean(false))
+}
+
+// eq? and eqv? are the same, but each reports errors under its own name
+fn compare_eqv(name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to {}: {:?}", name, args);
+    }
+    Ok(Value::Boolean(values_eqv(&args[0], &args[1])))
+}
+
+fn native_eq(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    compare_eqv("eq?", args)
+}
+
+fn native_eqv(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    compare_eqv("eqv?", args)
+}
+
+fn native_equal_values(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to equal?: {:?}", args);
+    }
+    let vals = args.to_vec();
+    Ok(Value::Boolean(vals[0] == vals[1]))
+}
+
+// Return the first sublist whose car matches x, or #f
+fn member_list(x: &Value, list: Vec<Value>, compare: &Option<Function>, env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    for i in 0..list.len() {
+        if try!(compare_values(compare, x, &list[i], env.clone())) {
+            return Ok(Value::List(list.into_iter().skip(i).collect()));
+        }
+    }
+    Ok(Value::Boolean(false))
+}
+
+fn member_eqv(name: &str, args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to {}: {:?}", name, args);
+    }
+    let (x, list, _) = try!(search_args(name, args));
+    member_list(&x, list, &None, env)
+}
+
+fn native_memq(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    member_eqv("memq", args, env)
+}
+
+fn native_memv(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    member_eqv("memv", args, env)
+}
+
+fn native_member(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let (x, list, compare) = try!(search_args("member", args));
+    member_list(&x, list, &compare, env)
+}
+
+// Return the first pair in an association list whose key matches x, or #f
+fn assoc_list(name: &str, x: &Value, list: Vec<Value>, compare: &Option<Function>, env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    for entry in list.into_iter() {
+        let matches = match entry {
+            Value::List(ref pair) if pair.len() > 0 => try!(compare_values(compare, x, &pair[0], env.clone())),
+            _ => runtime_error!("Must supply an association list to {}: {:?}", name, entry)
+        };
+        if matches {
+            return Ok(entry);
+        }
+    }
+    Ok(Value::Boolean(false))
+}
+
+fn assoc_eqv(name: &str, args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to {}: {:?}", name, args);
+    }
+    let (x, list, _) = try!(search_args(name, args));
+    assoc_list(name, &x, list, &None, env)
+}
+
+fn native_assq(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    assoc_eqv("assq", args, env)
+}
+
+fn native_assv(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    assoc_eqv("assv", args, env)
+}
+
+fn native_assoc(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let (x, list, compare) = try!(search_args("assoc", args));
+    assoc_list("assoc", &x, list, &compare, env)
+}
+
+fn native_quote(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to quote: {:?}", args);
+    }
+    quote_value(&args[0], false, env.clone())
+}
+
+fn native_quasiquote(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to quasiquote: {:?}", args);
+    }
+    quote_value(&args[0], true, env.clone())
+}
+
+fn native_error(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one arguments to error: {:?}", args);
+    }
+    runtime_error!("{:?}", args[0]);
+}
+
+fn native_apply(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() < 2 {
+        runtime_error!("Must supply at least two arguments to apply: {:?}", args);
+    }
+    let func = match args[0] {
+        Value::Procedure(ref func) => func,
+        _ => runtime_error!("First argument to apply must be a procedure: {:?}", args)
+    };
+
+    // (apply <proc> <arg> ... <list>) -- any arguments before the final list are prepended to it
+    let mut func_args = (&args[1..args.len() - 1]).to_vec();
+    match args[args.len() - 1] {
+        Value::List(ref list) => func_args.extend(list.iter().cloned()),
+        _ => runtime_error!("Last argument to apply must be a list of arguments: {:?}", args)
+    };
+    apply_function(func, func_args, env)
+}
+
+fn native_eval(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() < 1 || args.len() > 2 {
+        runtime_error!("Must supply one or two arguments to eval: {:?}", args);
+    }
+
+    // the argument has already been evaluated into data using the local environment, so evaluate that data as code
+    // using the given environment, or the global environment if there isn't one
+    let eval_env = if args.len() == 2 {
+        try!(expect_environment("eval", &args[1]))
+    } else {
+        Environment::get_root(env)
+    };
+    evaluate_value(&args[0], eval_env)
+}
+
+fn native_macroexpand_1(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to macroexpand-1: {:?}", args);
+    }
+    match try!(macroexpand_1(&args[0], env)) {
+        Some(expanded) => Ok(expanded),
+        None => Ok(args[0].clone())
+    }
+}
+
+fn native_macroexpand(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to macroexpand: {:?}", args);
+    }
+    macroexpand(&args[0], env)
+}
+
+fn native_expand(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to expand: {:?}", args);
+    }
+    expand_all(&args[0], env)
+}
+
+fn expect_environment(name: &str, v: &Value) -> Result<Rc<RefCell<Environment>>, RuntimeError> {
+    match *v {
+        Value::Environment(ref e) => Ok(e.clone()),
+        _ => runtime_error!("Must supply an environment to {}: {:?}", name, v)
+    }
+}
+
+fn expect_report_version(name: &str, args: &[Value]) -> Result<(), RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to {}: {:?}", name, args);
+    }
+    match args[0] {
+        Value::Integer(5) => Ok(()),
+        _ => runtime_error!("Unsupported version in {}: {:?}", name, args[0])
+    }
+}
+
+// A fresh environment with all of the standard bindings, which is unaffected by any definitions made elsewhere
+fn native_scheme_report_environment(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    try!(expect_report_version("scheme-report-environment", args));
+    Ok(Value::Environment(Environment::new_root(StandardPorts::of(&env))))
+}
+
+// Syntactic keywords aren't bound in environments, so the null environment is simply one without any variables
+fn native_null_environment(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    try!(expect_report_version("null-environment", args));
+    Ok(Value::Environment(Environment::new_empty(StandardPorts::of(&env))))
+}
+
+fn native_interaction_environment(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 0 {
+        runtime_error!("Must supply exactly zero arguments to interaction-environment: {:?}", args);
+    }
+    Ok(Value::Environment(Environment::get_root(env)))
+}
+
+fn native_the_environment(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 0 {
+        runtime_error!("Must supply exactly zero arguments to the-environment: {:?}", args);
+    }
+    Ok(Value::Environment(env))
+}
+
+// The bindings exported by each library that can be imported with environment; (rusty-scheme) exports all of them
+const LIBRARIES: &'static [(&'static str, &'static [&'static str])] = &[
+    ("scheme base", &["+", "-", "*", "/", "<", ">", "=", "<=", ">=", "quotient", "remainder", "modulo", "floor/",
+                      "truncate/", "abs", "min", "max", "gcd", "lcm", "expt", "exact-integer-sqrt", "zero?",
+                      "positive?", "negative?", "odd?", "even?", "number?", "integer?", "rational?", "numerator",
+                      "denominator", "floor", "ceiling", "round", "truncate", "exact", "inexact", "square", "complex?",
+                      "real?", "null?", "list", "car", "cdr", "cons", "append", "length",
+                      "reverse", "list-ref", "list-tail", "map", "for-each", "memq", "memv", "member", "assq", "assv",
+                      "assoc", "eq?", "eqv?", "equal?", "vector", "make-vector", "vector?", "vector-length",
+                      "vector-ref", "vector-set!", "vector->list", "list->vector", "apply", "error", "make-parameter",
+                      "current-input-port", "current-output-port", "current-error-port", "port?", "input-port?",
+                      "output-port?", "open-input-string", "open-output-string", "get-output-string", "close-port",
+                      "close-input-port", "close-output-port", "char?", "read-char", "peek-char", "read-line",
+                      "read-string", "char-ready?", "eof-object", "eof-object?", "bytevector?", "bytevector",
+                      "make-bytevector", "bytevector-length", "bytevector-u8-ref", "bytevector-u8-set!",
+                      "bytevector-copy", "bytevector-append", "utf8->string", "string->utf8", "open-input-bytevector",
+                      "open-output-bytevector", "get-output-bytevector", "read-u8", "peek-u8", "read-bytevector",
+                      "write-u8", "write-bytevector"]),
+    ("scheme inexact", &["sqrt", "exp", "log", "sin", "cos", "tan", "asin", "acos", "atan"]),
+    ("scheme complex", &["make-rectangular", "make-polar", "real-part", "imag-part", "magnitude", "angle"]),
+    ("scheme file", &["open-input-file", "open-output-file", "call-with-input-file", "call-with-output-file",
+                      "with-input-from-file", "with-output-to-file", "file-exists?", "delete-file",
+                      "open-binary-input-file", "open-binary-output-file"]),
+    ("scheme write", &["write", "write-shared", "write-simple", "display", "newline"]),
+    ("scheme read", &["read"]),
+    ("scheme eval", &["eval", "environment"]),
+    ("scheme repl", &["interaction-environment"]),
+    ("scheme r5rs", &["scheme-report-environment", "null-environment"]),
+    ("srfi 28", &["format"]),
+    ("srfi 1", &["iota", "last", "filter", "remove", "partition", "fold", "fold-right", "reduce", "any", "every",
+                 "find", "delete", "delete-duplicates"]),
+    ("srfi 151", &["bitwise-and", "bitwise-or", "bitwise-xor", "bitwise-not", "arithmetic-shift", "bit-count",
+                   "integer-length", "bit-set?", "copy-bit", "bit-field"]),
+    ("srfi 95", &["sort", "sort!", "merge"]),
+    ("srfi 132", &["list-sort", "vector-sort", "vector-binary-search"]),
+];
+
+// Resolve an import set, such as (scheme base) or (only (scheme base) car cdr), to the bindings it imports
+fn import_set(spec: &Value, all: &Environment) -> Result<Vec<(String, Value)>, RuntimeError> {
+    let list = match *spec {
+        Value::List(ref list) if list.len() > 0 => list,
+        _ => runtime_error!("Unexpected import set in environment: {:?}", spec)
+    };
+    let modifier = match (&list[0], list.get(1)) {
+        (&Value::Symbol(ref s), Some(&Value::List(_))) => s.as_str(),
+        _ => ""
+    };
+    let symbols = |values: &[Value]| -> Result<Vec<String>, RuntimeError> {
+        values.iter().map(|v| match *v {
+            Value::Symbol(ref s) => Ok(s.clone()),
+            _ => runtime_error!("Unexpected identifier in import set: {:?}", v)
+        }).collect()
+    };
+    match modifier {
+        "only" => {
+            let names = try!(symbols(&list[2..]));
+            let bindings = try!(import_set(&list[1], all));
+            for name in names.iter() {
+                if !bindings.iter().any(|&(ref n, _)| n == name) {
+                    runtime_error!("Identifier not found in import set: {}", name);
+                }
+            }
+            Ok(bindings.into_iter().filter(|&(ref n, _)| names.contains(n)).collect())
+        },
+        "except" => {
+            let names = try!(symbols(&list[2..]));
+            let bindings = try!(import_set(&list[1], all));
+            Ok(bindings.into_iter().filter(|&(ref n, _)| !names.contains(n)).collect())
+        },
+        "prefix" => {
+            let prefix = try!(symbols(&list[2..]));
+            if prefix.len() != 1 {
+                runtime_error!("Must supply exactly one prefix in import set: {:?}", spec);
+            }
+            let bindings = try!(import_set(&list[1], all));
+            Ok(bindings.into_iter().map(|(n, v)| (format!("{}{}", prefix[0], n), v)).collect())
+        },
+        "rename" => {
+            let mut bindings = try!(import_set(&list[1], all));
+            for pair in (&list[2..]).iter() {
+                let names = match *pair {
+                    Value::List(ref names) if names.len() == 2 => try!(symbols(names)),
+                    _ => runtime_error!("Unexpected rename in import set: {:?}", pair)
+                };
+                match bindings.iter_mut().find(|&&mut (ref n, _)| *n == names[0]) {
+                    Some(binding) => binding.0 = names[1].clone(),
+                    None => runtime_error!("Identifier not found in import set: {}", names[0])
+                }
+            }
+            Ok(bindings)
+        },
+        _ => {
+            let parts: Vec<String> = list.iter().map(|v| format!("{}", v)).collect();
+            let library = parts.join(" ");
+            let names: Vec<String> = if library == "rusty-scheme" {
+                all.values.keys().cloned().collect()
+            } else {
+                match LIBRARIES.iter().find(|&&(name, _)| name == library) {
+                    Some(&(_, names)) => names.iter().map(|n| n.to_string()).collect(),
+                    None => runtime_error!("Unknown library: {:?}", spec)
+                }
+            };
+            Ok(names.into_iter().map(|n| {
+                let val = all.values[&n].clone();
+                (n, val)
+            }).collect())
+        }
+    }
+}
+
+fn native_environment(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let ports = StandardPorts::of(&env);
+    let all = Environment::new_root(ports.clone());
+    let env = Environment::new_empty(ports);
+    for spec in args.iter() {
+        for (name, val) in try!(import_set(spec, &all.borrow())).into_iter() {
+            try!(env.borrow_mut().define(name, val));
+        }
+    }
+    Ok(Value::Environment(env))
+}
+
+fn native_environment_bound(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to environment-bound?: {:?}", args);
+    }
+    let env = try!(expect_environment("environment-bound?", &args[0]));
+    let bound = match args[1] {
+        Value::Symbol(ref name) => {
+            // syntactic keywords are available in every environment
+            let env = env.borrow();
+            special_form(name).is_some() || env.get(name).is_some() || env.is_unassigned(name)
+        },
+        _ => runtime_error!("Must supply a symbol to environment-bound?: {:?}", args[1])
+    };
+    Ok(Value::Boolean(bound))
+}
+
+fn native_environment_define(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 3 {
+        runtime_error!("Must supply exactly three arguments to environment-define!: {:?}", args);
+    }
+    let env = try!(expect_environment("environment-define!", &args[0]));
+    match args[1] {
+        Value::Symbol(ref name) => try!(env.borrow_mut().define(name.clone(), args[2].clone())),
+        _ => runtime_error!("Must supply a symbol to environment-define!: {:?}", args[1])
+    };
+    Ok(Value::null())
+}
+
+// The port given as an optional argument to an output procedure, or the current output port otherwise
+fn output_port_arg(name: &str, arg: Option<&Value>, env: &Rc<RefCell<Environment>>) -> Result<Rc<RefCell<OutputPort>>, RuntimeError> {
+    let port = match arg {
+        Some(port) => port.clone(),
+        None => StandardPorts::of(env).output.value.borrow().clone()
+    };
+    match port {
+        Value::Port(Port::Output(p)) => Ok(p),
+        _ => runtime_error!("Must supply an output port to {}: {:?}", name, port)
+    }
+}
+
+// Write the text for the first argument to the port given as the optional second argument
+fn print_with(name: &str, args: &[Value], env: &Rc<RefCell<Environment>>, text: &Fn(&Value) -> String) -> Result<Value, RuntimeError> {
+    if args.len() < 1 || args.len() > 2 {
+        runtime_error!("Must supply one or two arguments to {}: {:?}", name, args);
+    }
+
+    let port = try!(output_port_arg(name, args.get(1), env));
+    try!(port.borrow_mut().write_str(&text(&args[0])));
+    Ok(Value::null())
+}
+
+fn native_write(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    print_with("write", args, &env, &|val| format!("{:?}", val))
+}
+
+fn native_display(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    print_with("display", args, &env, &|val| format!("{}", val))
+}
+
+fn native_displayln(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    print_with("displayln", args, &env, &|val| format!("{}\n", val))
+}
+
+// Racket's print convention: anything that would evaluate differently as an expression gets a leading quote,
+// while everything inside it is written as-is
+fn print_quoted(val: &Value) -> String {
+    match *val {
+        Value::Symbol(_) | Value::List(_) | Value::Vector(_) => format!("'{:?}", val),
+        _ => format!("{:?}", val)
+    }
+}
+
+fn native_print(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    print_with("print", args, &env, &print_quoted)
+}
+
+fn native_write_shared(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    print_with("write-shared", args, &env, &|val| print_value(val, true, Labels::Shared))
+}
+
+fn native_write_simple(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    // without labels, cyclic structure can't be printed at all
+    if let Some(val) = args.first() {
+        if is_cyclic(val) {
+            runtime_error!("Can't write-simple a cyclic structure: {:?}", val);
+        }
+    }
+    print_with("write-simple", args, &env, &|val| print_value(val, true, Labels::Never))
+}
+
+fn native_newline(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() > 1 {
+        runtime_error!("Must supply zero or one arguments to newline: {:?}", args);
+    }
+    let port = try!(output_port_arg("newline", args.get(0), &env));
+    try!(port.borrow_mut().write_str("\n"));
+    Ok(Value::null())
+}
+
+// Padding is built up in memory, so widths are kept to something sensible
+const MAX_FORMAT_WIDTH: usize = 10000;
+
+// Fill in a format string's directives: ~a displays, ~s writes and ~v prints the next argument, ~d, ~b, ~o
+// and ~x write an integer in decimal, binary, octal or hex, and ~% and ~~ are a newline and a tilde.
+// A width, as in ~10a or ~8,'0x, pads the result to that many characters with spaces or the given character;
+// text is left-aligned and numbers right-aligned, unless @ switches it (for numbers @ also shows a + sign)
+fn format_directives(name: &str, format: &str, args: &[Value]) -> Result<String, RuntimeError> {
+    let mut res = String::new();
+    let mut args = args.iter();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '~' {
+            res.push(c);
+            continue;
+        }
+
+        let mut width = String::new();
+        while let Some(&d) = chars.peek() {
+            if !d.is_digit(10) {
+                break;
+            }
+            width.push(d);
+            chars.next();
+        }
+        let width: usize = match width.parse() {
+            Ok(width) if width <= MAX_FORMAT_WIDTH => width,
+            Ok(width) => runtime_error!("Width {} is too large in the format string given to {}: {:?}", width, name, format),
+            Err(_) if width.is_empty() => 0,
+            Err(_) => runtime_error!("Malformed width in the format string given to {}: {:?}", name, format)
+        };
+        let mut pad = ' ';
+        if chars.peek() == Some(&',') {
+            chars.next();
+            match (chars.next(), chars.next()) {
+                (Some('\''), Some(p)) => pad = p,
+                _ => runtime_error!("Expected a quoted padding character after , in {} string: {:?}", name, format)
+            }
+        }
+        let at = chars.peek() == Some(&'@');
+        if at {
+            chars.next();
+        }
+
+        let directive = match chars.next() {
+            Some(d) => d.to_ascii_lowercase(),
+            None => runtime_error!("Format string for {} ends in the middle of a directive: {:?}", name, format)
+        };
+        let (text, right_align) = match directive {
+            '%' | 'n' => ("\n".to_string(), false),
+            '~' => ("~".to_string(), false),
+            'a' | 's' | 'v' | 'd' | 'b' | 'o' | 'x' => {
+                let arg = match args.next() {
+                    Some(arg) => arg,
+                    None => runtime_error!("Not enough arguments for the format string given to {}: {:?}", name, format)
+                };
+                match directive {
+                    'a' => (format!("{}", arg), at),
+                    's' => (format!("{:?}", arg), at),
+                    'v' => (print_quoted(arg), at),
+                    _ => {
+                        let radix = match directive { 'b' => 2, 'o' => 8, 'x' => 16, _ => 10 };
+                        let text = match *arg {
+                            Value::Integer(i) => {
+                                let digits = match radix {
+                                    2 => format!("{:b}", i.unsigned_abs()),
+                                    8 => format!("{:o}", i.unsigned_abs()),
+                                    16 => format!("{:x}", i.unsigned_abs()),
+                                    _ => format!("{}", i.unsigned_abs())
+                                };
+                                let sign = if i < 0 { "-" } else if at { "+" } else { "" };
+                                format!("{}{}", sign, digits)
+                            },
+                            Value::Float(_) | Value::Rational(_, _) if radix == 10 => format!("{}", arg),
+                            _ => runtime_error!("Must supply an integer for ~{} in {}: {:?}", directive, name, arg)
+                        };
+                        (text, true)
+                    }
+                }
+            },
+            _ => runtime_error!("Unknown directive ~{} in the format string given to {}: {:?}", directive, name, format)
+        };
+
+        let len = text.chars().count();
+        let padding: String = if len < width { ::std::iter::repeat(pad).take(width - len).collect() } else { String::new() };
+        if right_align {
+            res.push_str(&padding);
+            res.push_str(&text);
+        } else {
+            res.push_str(&text);
+            res.push_str(&padding);
+        }
+    }
+    if args.next().is_some() {
+        runtime_error!("Too many arguments for the format string given to {}: {:?}", name, format);
+    }
+    Ok(res)
+}
+
+fn format_arg(name: &str, args: &[Value]) -> Result<String, RuntimeError> {
+    match args.get(0) {
+        Some(&Value::String(ref format)) => format_directives(name, format, &args[1..]),
+        _ => runtime_error!("Must supply a format string to {}: {:?}", name, args)
+    }
+}
+
+fn native_format(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    Ok(Value::String(try!(format_arg("format", args))))
+}
+
+fn native_printf(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let text = try!(format_arg("printf", args));
+    let port = try!(output_port_arg("printf", None, &env));
+    try!(port.borrow_mut().write_str(&text));
+    Ok(Value::null())
+}
+
+fn native_fprintf(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() < 2 {
+        runtime_error!("Must supply at least two arguments to fprintf: {:?}", args);
+    }
+    let port = try!(output_port_arg("fprintf", Some(&args[0]), &env));
+    let text = try!(format_arg("fprintf", &args[1..]));
+    try!(port.borrow_mut().write_str(&text));
+    Ok(Value::null())
+}
+
+fn native_is_port(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to port?: {:?}", args);
+    }
+    match args[0] {
+        Value::Port(_) => Ok(Value::Boolean(true)),
+        _ => Ok(Value::Boolean(false))
+    }
+}
+
+fn native_is_input_port(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to input-port?: {:?}", args);
+    }
+    match args[0] {
+        Value::Port(Port::Input(_)) => Ok(Value::Boolean(true)),
+        _ => Ok(Value::Boolean(false))
+    }
+}
+
+fn native_is_output_port(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to output-port?: {:?}", args);
+    }
+    match args[0] {
+        Value::Port(Port::Output(_)) => Ok(Value::Boolean(true)),
+        _ => Ok(Value::Boolean(false))
+    }
+}
+
+fn native_open_input_string(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to open-input-string: {:?}", args);
+    }
+    match args[0] {
+        Value::String(ref s) => Ok(new_input_port("string", Box::new(io::Cursor::new(s.clone().into_bytes())))),
+        _ => runtime_error!("Must supply a string to open-input-string: {:?}", args[0])
+    }
+}
+
+fn native_open_output_string(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 0 {
+        runtime_error!("Must supply exactly zero arguments to open-output-string: {:?}", args);
+    }
+    Ok(new_output_port("string", Sink::Buffer(Vec::new())))
+}
+
+// The text written so far to a port created by open-output-string
+fn output_string(name: &str, port: &Value) -> Result<String, RuntimeError> {
+    if let Value::Port(Port::Output(ref p)) = *port {
+        if let Sink::Buffer(ref buf) = p.borrow().sink {
+            return Ok(String::from_utf8_lossy(buf).into_owned());
+        }
+    }
+    runtime_error!("Must supply a string output port to {}: {:?}", name, port)
+}
+
+fn native_get_output_string(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to get-output-string: {:?}", args);
+    }
+    Ok(Value::String(try!(output_string("get-output-string", &args[0]))))
+}
+
+fn native_call_with_output_string(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to call-with-output-string: {:?}", args);
+    }
+    let func = match args[0] {
+        Value::Procedure(ref f) => f,
+        _ => runtime_error!("Must supply a procedure to call-with-output-string: {:?}", args[0])
+    };
+    let port = new_output_port("string", Sink::Buffer(Vec::new()));
+    try!(apply_function(func, vec![port.clone()], env));
+    Ok(Value::String(try!(output_string("call-with-output-string", &port))))
+}
+
+fn native_with_output_to_string(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to with-output-to-string: {:?}", args);
+    }
+    let func = match args[0] {
+        Value::Procedure(ref f) => f,
+        _ => runtime_error!("Must supply a procedure to with-output-to-string: {:?}", args[0])
+    };
+    let port = new_output_port("string", Sink::Buffer(Vec::new()));
+    let param = StandardPorts::of(&env).output.clone();
+    try!(with_parameter(&param, port.clone(), || apply_function(func, vec![], env)));
+    Ok(Value::String(try!(output_string("with-output-to-string", &port))))
+}
+
+fn native_with_input_from_string(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to with-input-from-string: {:?}", args);
+    }
+    let port = try!(native_open_input_string(&args[..1], env.clone()));
+    let func = match args[1] {
+        Value::Procedure(ref f) => f,
+        _ => runtime_error!("Must supply a procedure to with-input-from-string: {:?}", args[1])
+    };
+    let param = StandardPorts::of(&env).input.clone();
+    with_parameter(&param, port, || apply_function(func, vec![], env))
+}
+
+fn native_close_port(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to close-port: {:?}", args);
+    }
+    match args[0] {
+        Value::Port(ref p) => p.close(),
+        _ => runtime_error!("Must supply a port to close-port: {:?}", args[0])
+    }
+    Ok(Value::null())
+}
+
+fn native_close_input_port(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to close-input-port: {:?}", args);
+    }
+    match args[0] {
+        Value::Port(ref p @ Port::Input(_)) => p.close(),
+        _ => runtime_error!("Must supply an input port to close-input-port: {:?}", args[0])
+    }
+    Ok(Value::null())
+}
+
+fn native_close_output_port(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to close-output-port: {:?}", args);
+    }
+    match args[0] {
+        Value::Port(ref p @ Port::Output(_)) => p.close(),
+        _ => runtime_error!("Must supply an output port to close-output-port: {:?}", args[0])
+    }
+    Ok(Value::null())
+}
+
+// The port given as an optional argument to an input procedure, or the current input port otherwise
+fn input_port_arg(name: &str, arg: Option<&Value>, env: &Rc<RefCell<Environment>>) -> Result<Rc<RefCell<InputPort>>, RuntimeError> {
+    let port = match arg {
+        Some(port) => port.clone(),
+        None => StandardPorts::of(env).input.value.borrow().clone()
+    };
+    match port {
+        Value::Port(Port::Input(p)) => Ok(p),
+        _ => runtime_error!("Must supply an input port to {}: {:?}", name, port)
+    }
+}
+
+fn native_is_char(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to char?: {:?}", args);
+    }
+    match args[0] {
+        Value::Char(_) => Ok(Value::Boolean(true)),
+        _ => Ok(Value::Boolean(false))
+    }
+}
+
+fn native_read_char(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() > 1 {
+        runtime_error!("Must supply zero or one arguments to read-char: {:?}", args);
+    }
+    let port = try!(input_port_arg("read-char", args.get(0), &env));
+    let c = try!(port.borrow_mut().read_char());
+    Ok(c.map_or(Value::Eof, Value::Char))
+}
+
+fn native_peek_char(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() > 1 {
+        runtime_error!("Must supply zero or one arguments to peek-char: {:?}", args);
+    }
+    let port = try!(input_port_arg("peek-char", args.get(0), &env));
+    let c = try!(port.borrow_mut().peek_char());
+    Ok(c.map_or(Value::Eof, Value::Char))
+}
+
+fn native_read_line(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() > 1 {
+        runtime_error!("Must supply zero or one arguments to read-line: {:?}", args);
+    }
+    let port = try!(input_port_arg("read-line", args.get(0), &env));
+    let line = try!(port.borrow_mut().read_line());
+    Ok(line.map_or(Value::Eof, Value::String))
+}
+
+fn native_read_string(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() < 1 || args.len() > 2 {
+        runtime_error!("Must supply one or two arguments to read-string: {:?}", args);
+    }
+    let k = match args[0] {
+        Value::Integer(k) if k >= 0 => k as usize,
+        _ => runtime_error!("Must supply a non-negative count to read-string: {:?}", args[0])
+    };
+    let port = try!(input_port_arg("read-string", args.get(1), &env));
+    let s = try!(port.borrow_mut().read_string(k));
+    Ok(s.map_or(Value::Eof, Value::String))
+}
+
+fn native_char_ready(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() > 1 {
+        runtime_error!("Must supply zero or one arguments to char-ready?: {:?}", args);
+    }
+    let port = try!(input_port_arg("char-ready?", args.get(0), &env));
+    let ready = try!(port.borrow().char_ready());
+    Ok(Value::Boolean(ready))
+}
+
+fn native_read(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() > 1 {
+        runtime_error!("Must supply zero or one arguments to read: {:?}", args);
+    }
+    let port = try!(input_port_arg("read", args.get(0), &env));
+    let datum = try!(port.borrow_mut().read_datum());
+    Ok(datum.unwrap_or(Value::Eof))
+}
+
+fn native_read_from_string(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to read-from-string: {:?}", args);
+    }
+    let port = try!(native_open_input_string(args, env.clone()));
+    native_read(&[port], env)
+}
+
+fn native_eof_object(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 0 {
+        runtime_error!("Must supply exactly zero arguments to eof-object: {:?}", args);
+    }
+    Ok(Value::Eof)
+}
+
+fn native_is_eof_object(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to eof-object?: {:?}", args);
+    }
+    match args[0] {
+        Value::Eof => Ok(Value::Boolean(true)),
+        _ => Ok(Value::Boolean(false))
+    }
+}
+
+fn expect_bytevector(name: &str, v: &Value) -> Result<Rc<RefCell<Vec<u8>>>, RuntimeError> {
+    match *v {
+        Value::Bytevector(ref b) => Ok(b.clone()),
+        _ => runtime_error!("Must supply a bytevector to {}: {:?}", name, v)
+    }
+}
+
+fn expect_byte(name: &str, v: &Value) -> Result<u8, RuntimeError> {
+    match *v {
+        Value::Integer(i) if i >= 0 && i <= 255 => Ok(i as u8),
+        _ => runtime_error!("Must supply a byte to {}: {:?}", name, v)
+    }
+}
+
+// The optional start and end arguments to procedures like bytevector-copy, checked against the length
+fn expect_range(name: &str, args: &[Value], len: usize) -> Result<(usize, usize), RuntimeError> {
+    let start = match args.get(0) {
+        Some(v) => try!(expect_index(name, v.clone())),
+        None => 0
+    };
+    let end = match args.get(1) {
+        Some(v) => try!(expect_index(name, v.clone())),
+        None => len
+    };
+    if start > end || end > len {
+        runtime_error!("Range out of bounds in {}: {} to {}", name, start, end);
+    }
+    Ok((start, end))
+}
+
+fn new_bytevector(bytes: Vec<u8>) -> Value {
+    Value::Bytevector(Rc::new(RefCell::new(bytes)))
+}
+
+fn native_is_bytevector(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
     if args.len() != 1 {
-        runtime_error!("Must supply exactly one arguments to error: {:?}", args);
+        runtime_error!("Must supply exactly one argument to bytevector?: {:?}", args);
+    }
+    match args[0] {
+        Value::Bytevector(_) => Ok(Value::Boolean(true)),
+        _ => Ok(Value::Boolean(false))
     }
-    let e = try!(evaluate_value(&args[0], env.clone()));
-    runtime_error!("{:?}", e);
 }
 
-fn native_apply(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
-    if args.len() != 2 {
-        runtime_error!("Must supply exactly two arguments to apply: {:?}", args);
+fn native_bytevector(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let res: Result<Vec<u8>, RuntimeError> = args.iter().map(|v| expect_byte("bytevector", v)).collect();
+    Ok(new_bytevector(try!(res)))
+}
+
+fn native_make_bytevector(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() < 1 || args.len() > 2 {
+        runtime_error!("Must supply one or two arguments to make-bytevector: {:?}", args);
     }
-    let func = match try!(evaluate_value(&args[0], env.clone())) {
-        Value::Procedure(func) => func,
-        _ => runtime_error!("First argument to apply must be a procedure: {:?}", args)
-    };
-    let func_args = match try!(evaluate_value(&args[1], env.clone())) {
-        Value::List(func_args) => func_args,
-        _ => runtime_error!("Second argument to apply must be a list of arguments: {:?}", args)
+    let k = try!(expect_index("make-bytevector", args[0].clone()));
+    if k > MAX_VECTOR_LENGTH {
+        runtime_error!("Length {} is too large for make-bytevector", k);
+    }
+    let fill = match args.get(1) {
+        Some(v) => try!(expect_byte("make-bytevector", v)),
+        None => 0
     };
-    apply_function(&func, &func_args, env.clone())
+    Ok(new_bytevector(vec![fill; k]))
 }
 
-fn native_eval(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+fn native_bytevector_length(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
     if args.len() != 1 {
-        runtime_error!("Must supply exactly one argument to eval: {:?}", args);
+        runtime_error!("Must supply exactly one argument to bytevector-length: {:?}", args);
     }
+    let bytes = try!(expect_bytevector("bytevector-length", &args[0]));
+    let len = bytes.borrow().len();
+    Ok(Value::Integer(len as i64))
+}
 
-    // eval is basically just a double-evaluation -- the first evaluate returns the data using the local envirnoment, and the second evaluate evaluates the data as code using the global environment
-    let res = try!(evaluate_value(&args[0], env.clone()));
-    evaluate_value(&res, Environment::get_root(env))
+fn native_bytevector_u8_ref(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to bytevector-u8-ref: {:?}", args);
+    }
+    let bytes = try!(expect_bytevector("bytevector-u8-ref", &args[0]));
+    let k = try!(expect_index("bytevector-u8-ref", args[1].clone()));
+    let res = bytes.borrow().get(k).cloned();
+    match res {
+        Some(b) => Ok(Value::Integer(b as i64)),
+        None => runtime_error!("Index out of range in bytevector-u8-ref: {}", k)
+    }
 }
 
-fn native_write(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+fn native_bytevector_u8_set(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 3 {
+        runtime_error!("Must supply exactly three arguments to bytevector-u8-set!: {:?}", args);
+    }
+    let bytes = try!(expect_bytevector("bytevector-u8-set!", &args[0]));
+    let k = try!(expect_index("bytevector-u8-set!", args[1].clone()));
+    let byte = try!(expect_byte("bytevector-u8-set!", &args[2]));
+    let mut bytes = bytes.borrow_mut();
+    if k >= bytes.len() {
+        runtime_error!("Index out of range in bytevector-u8-set!: {}", k);
+    }
+    bytes[k] = byte;
+    Ok(Value::null())
+}
+
+fn native_bytevector_copy(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() < 1 || args.len() > 3 {
+        runtime_error!("Must supply one to three arguments to bytevector-copy: {:?}", args);
+    }
+    let bytes = try!(expect_bytevector("bytevector-copy", &args[0]));
+    let bytes = bytes.borrow();
+    let (start, end) = try!(expect_range("bytevector-copy", &args[1..], bytes.len()));
+    Ok(new_bytevector(bytes[start..end].to_vec()))
+}
+
+fn native_bytevector_append(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let mut res = Vec::new();
+    for arg in args.iter() {
+        let bytes = try!(expect_bytevector("bytevector-append", arg));
+        res.extend_from_slice(&bytes.borrow());
+    }
+    Ok(new_bytevector(res))
+}
+
+fn native_utf8_to_string(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() < 1 || args.len() > 3 {
+        runtime_error!("Must supply one to three arguments to utf8->string: {:?}", args);
+    }
+    let bytes = try!(expect_bytevector("utf8->string", &args[0]));
+    let bytes = bytes.borrow();
+    let (start, end) = try!(expect_range("utf8->string", &args[1..], bytes.len()));
+    match str::from_utf8(&bytes[start..end]) {
+        Ok(s) => Ok(Value::String(s.to_string())),
+        Err(e) => runtime_error!("Invalid UTF-8 in utf8->string: {}", e)
+    }
+}
+
+fn native_string_to_utf8(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
     if args.len() != 1 {
-        runtime_error!("Must supply exactly one argument to write: {:?}", args);
+        runtime_error!("Must supply exactly one argument to string->utf8: {:?}", args);
+    }
+    match args[0] {
+        Value::String(ref s) => Ok(new_bytevector(s.clone().into_bytes())),
+        _ => runtime_error!("Must supply a string to string->utf8: {:?}", args[0])
     }
+}
 
-    let val = try!(evaluate_value(&args[0], env.clone()));
-    print!("{:?}", val);
-    Ok(Value::null())
+fn native_open_input_bytevector(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to open-input-bytevector: {:?}", args);
+    }
+    let bytes = try!(expect_bytevector("open-input-bytevector", &args[0]));
+    let bytes = bytes.borrow().clone();
+    Ok(new_input_port("bytevector", Box::new(io::Cursor::new(bytes))))
 }
 
-fn native_display(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+fn native_open_output_bytevector(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 0 {
+        runtime_error!("Must supply exactly zero arguments to open-output-bytevector: {:?}", args);
+    }
+    Ok(new_output_port("bytevector", Sink::Buffer(Vec::new())))
+}
+
+fn native_get_output_bytevector(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
     if args.len() != 1 {
-        runtime_error!("Must supply exactly one argument to display: {:?}", args);
+        runtime_error!("Must supply exactly one argument to get-output-bytevector: {:?}", args);
+    }
+    if let Value::Port(Port::Output(ref p)) = args[0] {
+        if let Sink::Buffer(ref buf) = p.borrow().sink {
+            return Ok(new_bytevector(buf.clone()));
+        }
+    }
+    runtime_error!("Must supply a bytevector output port to get-output-bytevector: {:?}", args[0])
+}
+
+fn native_read_u8(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() > 1 {
+        runtime_error!("Must supply zero or one arguments to read-u8: {:?}", args);
+    }
+    let port = try!(input_port_arg("read-u8", args.get(0), &env));
+    let byte = try!(port.borrow_mut().read_u8());
+    Ok(byte.map_or(Value::Eof, |b| Value::Integer(b as i64)))
+}
+
+fn native_peek_u8(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() > 1 {
+        runtime_error!("Must supply zero or one arguments to peek-u8: {:?}", args);
+    }
+    let port = try!(input_port_arg("peek-u8", args.get(0), &env));
+    let byte = try!(port.borrow_mut().peek_u8());
+    Ok(byte.map_or(Value::Eof, |b| Value::Integer(b as i64)))
+}
+
+fn native_read_bytevector(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() < 1 || args.len() > 2 {
+        runtime_error!("Must supply one or two arguments to read-bytevector: {:?}", args);
     }
+    let k = try!(expect_index("read-bytevector", args[0].clone()));
+    let port = try!(input_port_arg("read-bytevector", args.get(1), &env));
+    let bytes = try!(port.borrow_mut().read_bytes(k));
+    Ok(bytes.map_or(Value::Eof, new_bytevector))
+}
 
-    let val = try!(evaluate_value(&args[0], env.clone()));
-    print!("{}", val);
+fn native_write_u8(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() < 1 || args.len() > 2 {
+        runtime_error!("Must supply one or two arguments to write-u8: {:?}", args);
+    }
+    let byte = try!(expect_byte("write-u8", &args[0]));
+    let port = try!(output_port_arg("write-u8", args.get(1), &env));
+    try!(port.borrow_mut().write_bytes(&[byte]));
     Ok(Value::null())
 }
 
-fn native_displayln(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+fn native_write_bytevector(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() < 1 || args.len() > 4 {
+        runtime_error!("Must supply one to four arguments to write-bytevector: {:?}", args);
+    }
+    let bytes = try!(expect_bytevector("write-bytevector", &args[0]));
+    let port = try!(output_port_arg("write-bytevector", args.get(1), &env));
+    let bytes = bytes.borrow();
+    let (start, end) = try!(expect_range("write-bytevector", &args[args.len().min(2)..], bytes.len()));
+    try!(port.borrow_mut().write_bytes(&bytes[start..end]));
+    Ok(Value::null())
+}
+
+fn path_arg(name: &str, arg: &Value) -> Result<String, RuntimeError> {
+    match *arg {
+        Value::String(ref s) => Ok(s.clone()),
+        _ => runtime_error!("Must supply a path string to {}: {:?}", name, arg)
+    }
+}
+
+fn open_input_file(name: &str, arg: &Value) -> Result<Value, RuntimeError> {
+    let path = try!(path_arg(name, arg));
+    match File::open(&path) {
+        Ok(file) => Ok(new_input_port(&path, Box::new(file))),
+        Err(e) => runtime_error!("Can't open file {:?} for reading: {}", path, e)
+    }
+}
+
+fn open_output_file(name: &str, arg: &Value) -> Result<Value, RuntimeError> {
+    let path = try!(path_arg(name, arg));
+    match File::create(&path) {
+        Ok(file) => Ok(new_output_port(&path, Sink::File(file))),
+        Err(e) => runtime_error!("Can't open file {:?} for writing: {}", path, e)
+    }
+}
+
+fn native_open_input_file(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
     if args.len() != 1 {
-        runtime_error!("Must supply exactly one argument to displayln: {:?}", args);
+        runtime_error!("Must supply exactly one argument to open-input-file: {:?}", args);
     }
+    open_input_file("open-input-file", &args[0])
+}
 
-    let val = try!(evaluate_value(&args[0], env.clone()));
-    println!("{}", val);
-    Ok(Value::null())
+fn native_open_output_file(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to open-output-file: {:?}", args);
+    }
+    open_output_file("open-output-file", &args[0])
 }
 
-fn native_print(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+// Call the procedure with the port, closing the port afterwards whether or not the call succeeds
+fn call_with_port(name: &str, port: Value, arg: &Value, env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let func = match *arg {
+        Value::Procedure(ref f) => f,
+        _ => runtime_error!("Must supply a procedure to {}: {:?}", name, arg)
+    };
+    let res = apply_function(func, vec![port.clone()], env);
+    if let Value::Port(ref p) = port {
+        p.close();
+    }
+    res
+}
+
+fn native_call_with_input_file(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to call-with-input-file: {:?}", args);
+    }
+    let port = try!(open_input_file("call-with-input-file", &args[0]));
+    call_with_port("call-with-input-file", port, &args[1], env)
+}
+
+fn native_call_with_output_file(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to call-with-output-file: {:?}", args);
+    }
+    let port = try!(open_output_file("call-with-output-file", &args[0]));
+    call_with_port("call-with-output-file", port, &args[1], env)
+}
+
+// Call the thunk with the port as the current input or output port, closing it afterwards
+fn with_port(name: &str, param: Rc<Parameter>, port: Value, arg: &Value, env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let func = match *arg {
+        Value::Procedure(ref f) => f,
+        _ => runtime_error!("Must supply a procedure to {}: {:?}", name, arg)
+    };
+    let res = with_parameter(&param, port.clone(), || apply_function(func, vec![], env));
+    if let Value::Port(ref p) = port {
+        p.close();
+    }
+    res
+}
+
+fn native_with_input_from_file(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to with-input-from-file: {:?}", args);
+    }
+    let port = try!(open_input_file("with-input-from-file", &args[0]));
+    with_port("with-input-from-file", StandardPorts::of(&env).input.clone(), port, &args[1], env)
+}
+
+fn native_with_output_to_file(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to with-output-to-file: {:?}", args);
+    }
+    let port = try!(open_output_file("with-output-to-file", &args[0]));
+    with_port("with-output-to-file", StandardPorts::of(&env).output.clone(), port, &args[1], env)
+}
+
+fn native_file_exists(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
     if args.len() != 1 {
-        runtime_error!("Must supply exactly one argument to print: {:?}", args);
+        runtime_error!("Must supply exactly one argument to file-exists?: {:?}", args);
     }
+    let path = try!(path_arg("file-exists?", &args[0]));
+    Ok(Value::Boolean(Path::new(&path).exists()))
+}
 
-    let val = try!(evaluate_value(&args[0], env.clone()));
-    match val {
-        Value::Symbol(_) | Value::List(_) => print!("'{:?}", val),
-        _ => print!("{:?}", val)
+fn native_directory_exists(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to directory-exists?: {:?}", args);
     }
-    Ok(Value::null())
+    let path = try!(path_arg("directory-exists?", &args[0]));
+    Ok(Value::Boolean(Path::new(&path).is_dir()))
 }
 
-#[allow(unused_variables)]
-fn native_newline(args: &[Value], env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
-    if args.len() != 0 {
-        runtime_error!("Must supply exactly zero arguments to newline: {:?}", args);
+fn native_delete_file(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to delete-file: {:?}", args);
+    }
+    let path = try!(path_arg("delete-file", &args[0]));
+    match fs::remove_file(&path) {
+        Ok(_) => Ok(Value::null()),
+        Err(e) => runtime_error!("Can't delete file {:?}: {}", path, e)
+    }
+}
+
+fn native_rename_file(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        runtime_error!("Must supply exactly two arguments to rename-file: {:?}", args);
+    }
+    let from = try!(path_arg("rename-file", &args[0]));
+    let to = try!(path_arg("rename-file", &args[1]));
+    match fs::rename(&from, &to) {
+        Ok(_) => Ok(Value::null()),
+        Err(e) => runtime_error!("Can't rename file {:?} to {:?}: {}", from, to, e)
+    }
+}
+
+fn native_directory_list(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() > 1 {
+        runtime_error!("Must supply zero or one arguments to directory-list: {:?}", args);
+    }
+    let path = match args.get(0) {
+        Some(arg) => try!(path_arg("directory-list", arg)),
+        None => ".".to_string()
+    };
+    let entries = match fs::read_dir(&path) {
+        Ok(entries) => entries,
+        Err(e) => runtime_error!("Can't list directory {:?}: {}", path, e)
+    };
+    let mut names = Vec::new();
+    for entry in entries {
+        match entry {
+            Ok(entry) => names.push(entry.file_name().to_string_lossy().into_owned()),
+            Err(e) => runtime_error!("Can't list directory {:?}: {}", path, e)
+        }
+    }
+    // read_dir gives no particular order, so sort for reproducibility
+    names.sort();
+    Ok(Value::List(names.into_iter().map(Value::String).collect()))
+}
+
+fn native_make_directory(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to make-directory: {:?}", args);
+    }
+    let path = try!(path_arg("make-directory", &args[0]));
+    match fs::create_dir(&path) {
+        Ok(_) => Ok(Value::null()),
+        Err(e) => runtime_error!("Can't make directory {:?}: {}", path, e)
+    }
+}
+
+fn native_file_size(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to file-size: {:?}", args);
+    }
+    let path = try!(path_arg("file-size", &args[0]));
+    match fs::metadata(&path) {
+        Ok(ref meta) if meta.is_file() => Ok(Value::Integer(meta.len() as i64)),
+        Ok(_) => runtime_error!("Can't get the size of {:?}: not a file", path),
+        Err(e) => runtime_error!("Can't get the size of {:?}: {}", path, e)
+    }
+}
+
+fn native_file_modify_seconds(args: &[Value], _env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    if args.len() != 1 {
+        runtime_error!("Must supply exactly one argument to file-or-directory-modify-seconds: {:?}", args);
+    }
+    let path = try!(path_arg("file-or-directory-modify-seconds", &args[0]));
+    let modified = fs::metadata(&path).and_then(|meta| meta.modified());
+    match modified {
+        Ok(time) => {
+            // times before the epoch come back as an error holding the negative offset
+            let secs = match time.duration_since(UNIX_EPOCH) {
+                Ok(d) => d.as_secs() as i64,
+                Err(e) => -(e.duration().as_secs() as i64)
+            };
+            Ok(Value::Integer(secs))
+        },
+        Err(e) => runtime_error!("Can't get the modification time of {:?}: {}", path, e)
     }
-    println!("");
-    Ok(Value::null())
 }
 
 #[test]
 fn test_interpreter_global_variables() {
-    assert_eq!(new().run(&[Node::List(vec![Node::Identifier("define".to_string()), Node::Identifier("x".to_string()), Node::Integer(2)]), Node::List(vec![Node::Identifier("+".to_string()), Node::Identifier("x".to_string()), Node::Identifier("x".to_string()), Node::Identifier("x".to_string())])]).unwrap(),
+    assert_eq!(Interpreter::new().run(&Value::from_nodes(&[Node::List(vec![Node::Identifier("define".to_string()), Node::Identifier("x".to_string()), Node::Integer(2)]), Node::List(vec![Node::Identifier("+".to_string()), Node::Identifier("x".to_string()), Node::Identifier("x".to_string()), Node::Identifier("x".to_string())])])).unwrap(),
                Value::Integer(6));
 }
 
 #[test]
 fn test_interpreter_global_function_definition() {
-    assert_eq!(new().run(&[Node::List(vec![Node::Identifier("define".to_string()), Node::Identifier("double".to_string()), Node::List(vec![Node::Identifier("lambda".to_string()), Node::List(vec![Node::Identifier("x".to_string())]), Node::List(vec![Node::Identifier("+".to_string()), Node::Identifier("x".to_string()), Node::Identifier("x".to_string())])])]), Node::List(vec![Node::Identifier("double".to_string()), Node::Integer(8)])]).unwrap(),
+    assert_eq!(Interpreter::new().run(&Value::from_nodes(&[Node::List(vec![Node::Identifier("define".to_string()), Node::Identifier("double".to_string()), Node::List(vec![Node::Identifier("lambda".to_string()), Node::List(vec![Node::Identifier("x".to_string())]), Node::List(vec![Node::Identifier("+".to_string()), Node::Identifier("x".to_string()), Node::Identifier("x".to_string())])])]), Node::List(vec![Node::Identifier("double".to_string()), Node::Integer(8)])])).unwrap(),
                Value::Integer(16));
 }
+
+#[test]
+fn test_interpreter_parameterize() {
+    let interpreter = Interpreter::new();
+    assert_eq!(interpreter.execute("(define p (make-parameter 10)) (parameterize ((p 20)) (p))").unwrap(),
+               Value::Integer(20));
+    assert_eq!(interpreter.execute("(p)").unwrap(),
+               Value::Integer(10));
+    assert_eq!(interpreter.execute("(define q (make-parameter 1 (lambda (x) (* x 2)))) (list (q) (parameterize ((q 5)) (q)))").unwrap(),
+               Value::List(vec![Value::Integer(2), Value::Integer(10)]));
+}
+
+#[test]
+fn test_interpreter_parameterize_restores_on_error() {
+    let interpreter = Interpreter::new();
+    assert_eq!(interpreter.execute("(define p (make-parameter 'outer)) (parameterize ((p 'inner)) (error \"fail\"))").err().unwrap(),
+               "RuntimeError: \"fail\"");
+    assert_eq!(interpreter.execute("(p)").unwrap(),
+               Value::Symbol("outer".to_string()));
+}
+
+#[test]
+fn test_interpreter_define_record_type() {
+    let interpreter = Interpreter::new();
+    interpreter.execute("(define-record-type <point> (make-point x y) point? (x point-x set-point-x!) (y point-y))").unwrap();
+    assert_eq!(interpreter.execute("(define p (make-point 1 2)) (list (point? p) (point? 5) (point-x p) (point-y p))").unwrap(),
+               Value::List(vec![Value::Boolean(true), Value::Boolean(false), Value::Integer(1), Value::Integer(2)]));
+    assert_eq!(interpreter.execute("(set-point-x! p 10) (point-x p)").unwrap(),
+               Value::Integer(10));
+    assert_eq!(format!("{}", interpreter.execute("p").unwrap()),
+               "#<point 10 2>");
+    assert_eq!(interpreter.execute("(point-x 5)").err().unwrap(),
+               "RuntimeError: Must supply a point record to point-x: 5");
+}
+
+#[test]
+fn test_interpreter_list_library() {
+    let interpreter = Interpreter::new();
+    assert_eq!(interpreter.execute("(map + '(1 2 3) '(10 20))").unwrap(),
+               Value::List(vec![Value::Integer(11), Value::Integer(22)]));
+    assert_eq!(interpreter.execute("(map car '((a b) (c d)))").unwrap(),
+               Value::List(vec![Value::Symbol("a".to_string()), Value::Symbol("c".to_string())]));
+    assert_eq!(interpreter.execute("(filter (lambda (x) (> x 1)) '(1 2 3))").unwrap(),
+               Value::List(vec![Value::Integer(2), Value::Integer(3)]));
+    assert_eq!(interpreter.execute("(fold cons '() '(1 2 3))").unwrap(),
+               Value::List(vec![Value::Integer(3), Value::Integer(2), Value::Integer(1)]));
+    assert_eq!(interpreter.execute("(fold-right cons '() '(1 2 3))").unwrap(),
+               Value::List(vec![Value::Integer(1), Value::Integer(2), Value::Integer(3)]));
+    assert_eq!(interpreter.execute("(reduce + 0 (iota 5 1))").unwrap(),
+               Value::Integer(15));
+    assert_eq!(interpreter.execute("(iota 3 9223372036854775807)").err().unwrap(),
+               "RuntimeError: Integer overflow in iota");
+    assert_eq!(interpreter.execute("(append '(1) '() '(2 3) '(4))").unwrap(),
+               Value::List(vec![Value::Integer(1), Value::Integer(2), Value::Integer(3), Value::Integer(4)]));
+    assert_eq!(interpreter.execute("(delete-duplicates '(a b a c b))").unwrap(),
+               Value::List(vec![Value::Symbol("a".to_string()), Value::Symbol("b".to_string()), Value::Symbol("c".to_string())]));
+}
+
+#[test]
+fn test_interpreter_list_searching() {
+    let interpreter = Interpreter::new();
+    assert_eq!(interpreter.execute("(assq 'b '((a 1) (b 2)))").unwrap(),
+               Value::List(vec![Value::Symbol("b".to_string()), Value::Integer(2)]));
+    assert_eq!(interpreter.execute("(member 2 '(1 2 3))").unwrap(),
+               Value::List(vec![Value::Integer(2), Value::Integer(3)]));
+    assert_eq!(interpreter.execute("(member 2 '(1 2 3) (lambda (a b) (< a b)))").unwrap(),
+               Value::List(vec![Value::Integer(3)]));
+    assert_eq!(interpreter.execute("(find (lambda (x) (> x 5)) '(1 2 3))").unwrap(),
+               Value::Boolean(false));
+    assert_eq!(interpreter.execute("(list (any null? '(1 () 2)) (every null? '(1 () 2)))").unwrap(),
+               Value::List(vec![Value::Boolean(true), Value::Boolean(false)]));
+    assert_eq!(interpreter.execute("(map 5 '(1 2))").err().unwrap(),
+               "RuntimeError: Must supply a procedure to map: 5");
+    assert_eq!(interpreter.execute("(memq 'a 'b)").err().unwrap(),
+               "RuntimeError: Must supply a list to memq: b");
+    assert_eq!(interpreter.execute("(assq 'a '(1))").err().unwrap(),
+               "RuntimeError: Must supply an association list to assq: 1");
+    assert_eq!(interpreter.execute("(eq? 1)").err().unwrap(),
+               "RuntimeError: Must supply exactly two arguments to eq?: [1]");
+}
+
+#[test]
+fn test_interpreter_sorting() {
+    let interpreter = Interpreter::new();
+    assert_eq!(interpreter.execute("(sort '(3 1 2) <)").unwrap(),
+               Value::List(vec![Value::Integer(1), Value::Integer(2), Value::Integer(3)]));
+    assert_eq!(interpreter.execute("(list-sort (lambda (a b) (< (car a) (car b))) '((2 a) (1 b) (2 c) (1 d)))").unwrap(),
+               Interpreter::new().execute("'((1 b) (1 d) (2 a) (2 c))").unwrap());
+    assert_eq!(interpreter.execute("(define v (vector 5 3 4)) (sort! v <) v").unwrap(),
+               Interpreter::new().execute("(vector 3 4 5)").unwrap());
+    assert_eq!(interpreter.execute("(merge '(1 3 5) '(2 4) <)").unwrap(),
+               Interpreter::new().execute("'(1 2 3 4 5)").unwrap());
+    assert_eq!(interpreter.execute("(list (vector-binary-search v 4 -) (vector-binary-search v 7 -))").unwrap(),
+               Value::List(vec![Value::Integer(1), Value::Boolean(false)]));
+}
+
+#[test]
+fn test_interpreter_make_vector() {
+    let interpreter = Interpreter::new();
+    assert_eq!(format!("{:?}", interpreter.execute("(list (make-vector 2) (make-vector 3 'a))").unwrap()),
+               "(#(#f #f) #(a a a))");
+    assert_eq!(interpreter.execute("(make-vector 100000000000000)").err().unwrap(),
+               "RuntimeError: Length 100000000000000 is too large for make-vector");
+}
+
+#[test]
+fn test_interpreter_sorting_comparator_errors() {
+    let interpreter = Interpreter::new();
+    assert_eq!(interpreter.execute("(sort '(3 1 2) (lambda (a b) (error \"bad compare\")))").err().unwrap(),
+               "RuntimeError: \"bad compare\"");
+    assert!(interpreter.execute("(vector-sort < (vector 1 'a))").is_err());
+}
+
+#[test]
+fn test_interpreter_cyclic_vector_equality() {
+    let interpreter = Interpreter::new();
+    interpreter.execute("(define v (vector 1 2)) (vector-set! v 1 v) (define w (vector 1 2)) (vector-set! w 1 w)").unwrap();
+    assert_eq!(interpreter.execute("(list (equal? v v) (eq? (list v) (list v)) (equal? v w) (eqv? v w) (equal? v (vector 1 v)) (equal? v (vector 2 v)))").unwrap(),
+               Value::List(vec![Value::Boolean(true), Value::Boolean(true), Value::Boolean(true), Value::Boolean(false), Value::Boolean(true), Value::Boolean(false)]));
+}
+
+#[test]
+fn test_interpreter_primitives_receive_evaluated_arguments() {
+    let interpreter = Interpreter::new();
+    assert_eq!(interpreter.execute("(apply list '(a b))").unwrap(),
+               Value::List(vec![Value::Symbol("a".to_string()), Value::Symbol("b".to_string())]));
+    assert_eq!(interpreter.execute("(apply + 1 2 '(3 4))").unwrap(),
+               Value::Integer(10));
+    assert_eq!(interpreter.execute("(let ((add +)) (add 1 2))").unwrap(),
+               Value::Integer(3));
+    assert_eq!(interpreter.execute("(eval (list 'car ''(x y)))").unwrap(),
+               Value::Symbol("x".to_string()));
+}
+
+#[test]
+fn test_interpreter_syntactic_keywords() {
+    let interpreter = Interpreter::new();
+    assert_eq!(interpreter.execute("(define if 5)").err().unwrap(),
+               "RuntimeError: Can't bind a syntactic keyword: if");
+    assert_eq!(interpreter.execute("(set! lambda 5)").err().unwrap(),
+               "RuntimeError: Can't set! a syntactic keyword: lambda");
+    assert_eq!(interpreter.execute("(map if '(1 2))").err().unwrap(),
+               "RuntimeError: Syntactic keyword can't be used as a variable: if");
+    assert_eq!(interpreter.execute("(if #f 1 2)").unwrap(),
+               Value::Integer(2));
+    assert_eq!(interpreter.execute("(list ((lambda (if) if) 5) (let ((define 3)) define) (let ((list +)) (list 1 2)))").unwrap(),
+               Value::List(vec![Value::Integer(5), Value::Integer(3), Value::Integer(3)]));
+    assert_eq!(interpreter.execute("(define (f quote) (set! quote (+ quote 1)) (quote 2)) (f 1)").err().unwrap(),
+               "RuntimeError: First element in an expression must be a procedure: 2");
+    assert_eq!(interpreter.execute("(define (g) (define begin 4) begin) (list (g) (if #t 'ok 'no))").unwrap(),
+               Value::List(vec![Value::Integer(4), Value::Symbol("ok".to_string())]));
+}
+
+#[test]
+fn test_interpreter_internal_definitions() {
+    let interpreter = Interpreter::new();
+    assert_eq!(interpreter.execute("(define (f n) (define (ev? n) (if (= n 0) #t (od? (- n 1)))) (define (od? n) (if (= n 0) #f (ev? (- n 1)))) (ev? n)) (list (f 4) (f 7))").unwrap(),
+               Value::List(vec![Value::Boolean(true), Value::Boolean(false)]));
+    assert_eq!(interpreter.execute("(define x 1) (let () (begin (define x 2) (define y x)) (list x y))").unwrap(),
+               Value::List(vec![Value::Integer(2), Value::Integer(2)]));
+    assert_eq!(interpreter.execute("x").unwrap(),
+               Value::Integer(1));
+}
+
+#[test]
+fn test_interpreter_internal_definition_errors() {
+    let interpreter = Interpreter::new();
+    assert_eq!(interpreter.execute("(define (f) (+ 1 1) (define x 2) x) (f)").err().unwrap(),
+               "RuntimeError: Definitions must come before any expressions in a body: (define x 2)");
+    assert_eq!(interpreter.execute("(define x 1) ((lambda () (define y x) (define x 2) y))").err().unwrap(),
+               "RuntimeError: Variable used before its definition: x");
+    assert_eq!(interpreter.execute("((lambda () (define y 1)))").err().unwrap(),
+               "RuntimeError: Body must contain at least one expression after its definitions");
+}
+
+#[test]
+fn test_interpreter_eval_with_environments() {
+    let interpreter = Interpreter::new();
+    assert_eq!(interpreter.execute("(define (f x) (the-environment)) (eval '(* x 2) (f 21))").unwrap(),
+               Value::Integer(42));
+    assert_eq!(interpreter.execute("(define car cdr) (eval '(car '(1 2)) (scheme-report-environment 5))").unwrap(),
+               Value::Integer(1));
+    assert_eq!(interpreter.execute("(eval '(if #f 1 2) (null-environment 5))").unwrap(),
+               Value::Integer(2));
+    assert_eq!(interpreter.execute("(define e (environment '(only (scheme base) +) '(prefix (rename (scheme base) (car first)) my-)))
+                                    (environment-define! e 'x 10)
+                                    (list (eval '(+ x 1) e) (eval '(my-first '(a b)) e) (environment-bound? e 'car) (environment-bound? e 'if))").unwrap(),
+               Value::List(vec![Value::Integer(11), Value::Symbol("a".to_string()), Value::Boolean(false), Value::Boolean(true)]));
+    assert_eq!(interpreter.execute("(eval '(car '(1 2)) e)").err().unwrap(),
+               "RuntimeError: Identifier not found: car");
+    assert_eq!(interpreter.execute("(environment '(scheme nope))").err().unwrap(),
+               "RuntimeError: Unknown library: (scheme nope)");
+}
+
+#[test]
+fn test_interpreter_library_exports_are_defined() {
+    let root = Environment::new_root(StandardPorts::new());
+    for &(library, names) in LIBRARIES.iter() {
+        for name in names.iter() {
+            assert!(root.borrow().values().contains_key(*name), "{} exports undefined {}", library, name);
+        }
+    }
+}
+
+#[test]
+fn test_interpreter_macroexpand() {
+    let interpreter = Interpreter::new();
+    interpreter.execute("(define-syntax-rule (unless c body) (if c #f body)) (define-syntax-rule (my-not x) (unless x #t))").unwrap();
+    assert_eq!(format!("{:?}", interpreter.execute("(macroexpand-1 '(my-not (car l)))").unwrap()),
+               "(unless (car l) #t)");
+    assert_eq!(format!("{:?}", interpreter.execute("(macroexpand '(my-not (car l)))").unwrap()),
+               "(if (car l) #f #t)");
+    assert_eq!(format!("{:?}", interpreter.execute("(macroexpand '(car (my-not 1)))").unwrap()),
+               "(car (my-not 1))");
+    assert_eq!(format!("{:?}", interpreter.execute("(expand '(lambda (x) (my-not x) '(my-not x)))").unwrap()),
+               "(lambda (x) (if x #f #t) (quote (my-not x)))");
+    let expanded: Vec<String> = interpreter.expand("(let ((a (my-not 1))) a)").unwrap().iter().map(|v| format!("{:?}", v)).collect();
+    assert_eq!(expanded, vec!["(let ((a (if 1 #f #t))) a)"]);
+    assert_eq!(interpreter.execute("(macroexpand '(my-not 1 2))").err().unwrap(),
+               "RuntimeError: Must supply exactly 1 arguments to macro: [1, 2]");
+}
+
+#[test]
+fn test_interpreter_numeric_literals() {
+    let interpreter = Interpreter::new();
+    assert_eq!(interpreter.execute("(+ #xff #b11 #o10)").unwrap(),
+               Value::Integer(266));
+    assert_eq!(format!("{:?}", interpreter.execute("'(1.5 2.0 #i3 1e21 -inf.0 +nan.0)").unwrap()),
+               "(1.5 2.0 3.0 1e21 -inf.0 +nan.0)");
+    assert_eq!(interpreter.execute("(list (+ 1.5 #e2.0 #i1) (* 2 2.5e-1))").unwrap(),
+               Value::List(vec![Value::Float(4.5), Value::Float(0.5)]));
+    assert_eq!(interpreter.execute("(list (eqv? +nan.0 +nan.0) (equal? 0.0 -0.0) (= 0.0 -0.0) (= +nan.0 +nan.0))").unwrap(),
+               Value::List(vec![Value::Boolean(true), Value::Boolean(false), Value::Boolean(true), Value::Boolean(false)]));
+}
+
+#[test]
+fn test_interpreter_pipe_symbols() {
+    let interpreter = Interpreter::new();
+    assert_eq!(format!("{:?}", interpreter.execute("'(abc |hello world| |42| |a\\|b| || |#t|)").unwrap()),
+               "(abc |hello world| |42| |a\\|b| || |#t|)");
+    assert_eq!(format!("{:?}", interpreter.execute("'(+ - ... +inc |+i| |+inf.0| |-x| |.5| |a;b|)").unwrap()),
+               "(+ - ... +inc |+i| |+inf.0| |-x| |.5| |a;b|)");
+    assert_eq!(format!("{}", interpreter.execute("'|hello world|").unwrap()),
+               "hello world");
+    assert_eq!(interpreter.execute("(define |odd name| 5) |odd name|").unwrap(),
+               Value::Integer(5));
+
+    let mut folding = Interpreter::new();
+    folding.set_fold_case(true);
+    assert_eq!(folding.execute("(DEFINE X 2) (+ x X)").unwrap(),
+               Value::Integer(4));
+    assert_eq!(folding.execute("(eq? 'ABC 'abc)").unwrap(),
+               Value::Boolean(true));
+}
+
+#[test]
+fn test_interpreter_datum_labels() {
+    let interpreter = Interpreter::new();
+    assert_eq!(format!("{:?}", interpreter.execute("'(#0=(a b) #0# c)").unwrap()),
+               "((a b) (a b) c)");
+    assert_eq!(format!("{:?}", interpreter.execute("(define v (vector 1 2)) (vector-set! v 1 v) v").unwrap()),
+               "#0=#(1 #0#)");
+    assert_eq!(format!("{}", interpreter.execute("(list v v)").unwrap()),
+               "(#0=#(1 #0#) #0#)");
+
+    let shared = interpreter.execute("(define w (vector 1)) (list w w)").unwrap();
+    assert_eq!(print_value(&shared, true, Labels::Cycles), "(#(1) #(1))");
+    assert_eq!(print_value(&shared, true, Labels::Shared), "(#0=#(1) #0#)");
+    assert_eq!(print_value(&shared, true, Labels::Never), "(#(1) #(1))");
+    assert_eq!(format!("{:?}", interpreter.execute("(list #(1 #(a \"b\")) (vector? #()) (read (open-input-string \"#0=#(#0# 2)\")))").unwrap()),
+               "(#(1 #(a \"b\")) #t #0=#(#0# 2))");
+    assert_eq!(interpreter.execute("(define c '#0=#(1 #0#)) (define s '(#1=#(1) #1#)) (list (eq? c (vector-ref c 1)) (eq? (car s) (car (cdr s))) (equal? c '#2=#(1 #2#)))").unwrap(),
+               Value::List(vec![Value::Boolean(true), Value::Boolean(true), Value::Boolean(true)]));
+    assert_eq!(interpreter.execute("(define v (vector 1 2)) (vector-set! v 0 v) (write-simple v)").err().unwrap(),
+               "RuntimeError: Can't write-simple a cyclic structure: #0=#(#0# 2)");
+}
+
+#[test]
+fn test_interpreter_printing() {
+    let interpreter = Interpreter::new();
+    let list = interpreter.execute("(list 'blah 23 #t \"bl\\\"ah\\n\" (list '|a b| (vector 1)))").unwrap();
+    assert_eq!(format!("{:?}", list), "(blah 23 #t \"bl\\\"ah\\n\" (|a b| #(1)))");
+    assert_eq!(format!("{}", list), "(blah 23 #t bl\"ah\n (a b #(1)))");
+    assert_eq!(print_quoted(&list), "'(blah 23 #t \"bl\\\"ah\\n\" (|a b| #(1)))");
+    assert_eq!(print_quoted(&interpreter.execute("(vector 'a)").unwrap()), "'#(a)");
+    assert_eq!(print_quoted(&Value::String("blah".to_string())), "\"blah\"");
+
+    assert_eq!(format!("{}", interpreter.execute("(define (dump x) x) dump").unwrap()),
+               "#<procedure:dump>");
+    assert_eq!(format!("{:?}", interpreter.execute("(define f (lambda (x) x)) (define g f) g").unwrap()),
+               "#<procedure:f>");
+    assert_eq!(format!("{:?}", interpreter.execute("(lambda (x) x)").unwrap()),
+               "#<procedure>");
+}
+
+#[test]
+fn test_interpreter_ports() {
+    let interpreter = Interpreter::new();
+    assert_eq!(interpreter.execute("(list (output-port? (current-output-port)) (input-port? (current-input-port)) (port? 5))").unwrap(),
+               Value::List(vec![Value::Boolean(true), Value::Boolean(true), Value::Boolean(false)]));
+    assert_eq!(format!("{}", interpreter.execute("(current-error-port)").unwrap()),
+               "#<output-port:stderr>");
+    assert_eq!(interpreter.execute("(display \"to stderr\" (current-error-port)) (newline (current-error-port))").unwrap(),
+               Value::null());
+    assert_eq!(interpreter.execute("(parameterize ((current-output-port (current-error-port))) (eq? (current-output-port) (current-error-port)))").unwrap(),
+               Value::Boolean(true));
+    assert_eq!(interpreter.execute("(write 1 (current-input-port))").err().unwrap(),
+               "RuntimeError: Must supply an output port to write: #<input-port:stdin>");
+}
+
+#[test]
+fn test_interpreter_ports_belong_to_one_interpreter() {
+    let first = Interpreter::new();
+    let second = Interpreter::new();
+    first.execute("(close-port (current-input-port))").unwrap();
+    assert_eq!(first.execute("(char-ready?)").err().unwrap(),
+               "RuntimeError: Can't read from a closed port: stdin");
+    assert_eq!(second.execute("(char-ready?)").unwrap(),
+               Value::Boolean(false));
+    assert_eq!(first.execute("(with-output-to-string (lambda () (display \"a\") (eval '(display \"b\") (scheme-report-environment 5))))").unwrap(),
+               Value::String("ab".to_string()));
+}
+
+#[test]
+fn test_interpreter_string_ports() {
+    let interpreter = Interpreter::new();
+    assert_eq!(interpreter.execute("(define p (open-output-string)) (write \"a\" p) (display 'b p) (newline p) (get-output-string p)").unwrap(),
+               Value::String("\"a\"b\n".to_string()));
+    assert_eq!(interpreter.execute("(call-with-output-string (lambda (port) (print '(1 x) port)))").unwrap(),
+               Value::String("'(1 x)".to_string()));
+    assert_eq!(interpreter.execute("(with-output-to-string (lambda () (display 1) (displayln \"two\")))").unwrap(),
+               Value::String("1two\n".to_string()));
+    assert_eq!(interpreter.execute("(with-input-from-string \"abc\" (lambda () (input-port? (current-input-port))))").unwrap(),
+               Value::Boolean(true));
+
+    // the current output port is restored even when the thunk fails
+    assert!(interpreter.execute("(with-output-to-string (lambda () (car '())))").is_err());
+    assert_eq!(format!("{}", interpreter.execute("(current-output-port)").unwrap()),
+               "#<output-port:stdout>");
+    assert_eq!(interpreter.execute("(get-output-string (current-output-port))").err().unwrap(),
+               "RuntimeError: Must supply a string output port to get-output-string: #<output-port:stdout>");
+}
+
+#[test]
+fn test_interpreter_file_ports() {
+    let dir = ::std::env::temp_dir().join(format!("rusty_scheme_test_{}", ::std::process::id()));
+    let interpreter = Interpreter::new();
+    interpreter.execute(&format!("(define dir {:?})", dir.to_str().unwrap())).unwrap();
+    assert_eq!(interpreter.execute("(make-directory dir) (directory-exists? dir)").unwrap(),
+               Value::Boolean(true));
+    interpreter.execute(&format!("(define a {:?}) (define b {:?}) (define c {:?})", dir.join("a.txt").to_str().unwrap(),
+                                 dir.join("b.txt").to_str().unwrap(), dir.join("c.txt").to_str().unwrap())).unwrap();
+    assert_eq!(interpreter.execute("(with-output-to-file a (lambda () (display \"hello\"))) (file-size a)").unwrap(),
+               Value::Integer(5));
+    assert_eq!(interpreter.execute("(call-with-output-file b (lambda (p) (write 'x p) (newline p))) (list (file-size b) (> (file-or-directory-modify-seconds b) 0))").unwrap(),
+               Value::List(vec![Value::Integer(2), Value::Boolean(true)]));
+    assert_eq!(interpreter.execute("(rename-file b c) (directory-list dir)").unwrap(),
+               Value::List(vec![Value::String("a.txt".to_string()), Value::String("c.txt".to_string())]));
+    assert_eq!(interpreter.execute("(define p (open-output-file a)) (close-port p) (write 1 p)").err().unwrap(),
+               format!("RuntimeError: Can't write to a closed port: {}/a.txt", dir.to_str().unwrap()));
+    assert_eq!(interpreter.execute("(define p (open-output-file a)) (close-input-port p)").err().unwrap(),
+               format!("RuntimeError: Must supply an input port to close-input-port: #<output-port:{}/a.txt>", dir.to_str().unwrap()));
+    assert_eq!(interpreter.execute("(close-output-port p) (define p (open-input-file a)) (close-output-port p)").err().unwrap(),
+               format!("RuntimeError: Must supply an output port to close-output-port: #<input-port:{}/a.txt>", dir.to_str().unwrap()));
+    interpreter.execute("(close-input-port p)").unwrap();
+    assert_eq!(interpreter.execute("(delete-file a) (list (file-exists? a) (file-exists? c) (file-exists? dir))").unwrap(),
+               Value::List(vec![Value::Boolean(false), Value::Boolean(true), Value::Boolean(true)]));
+    assert!(interpreter.execute("(delete-file a)").err().unwrap().starts_with("RuntimeError: Can't delete file"));
+    assert!(interpreter.execute("(open-input-file a)").err().unwrap().starts_with("RuntimeError: Can't open file"));
+    ::std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_interpreter_character_input() {
+    let interpreter = Interpreter::new();
+    interpreter.execute("(define p (open-input-string \"ab\\ncd\\r\\n\\nλx\"))").unwrap();
+    assert_eq!(interpreter.execute("(list (peek-char p) (read-char p) (read-char p) (char-ready? p))").unwrap(),
+               Value::List(vec![Value::Char('a'), Value::Char('a'), Value::Char('b'), Value::Boolean(true)]));
+    assert_eq!(interpreter.execute("(list (read-line p) (read-line p) (read-line p) (read-string 5 p) (read-line p))").unwrap(),
+               Value::List(vec![Value::String("".to_string()), Value::String("cd".to_string()), Value::String("".to_string()),
+                                Value::String("λx".to_string()), Value::Eof]));
+    assert_eq!(interpreter.execute("(list (eof-object? (read-char p)) (eof-object? (peek-char p)) (eof-object? (eof-object)) (eof-object? #\\a))").unwrap(),
+               Value::List(vec![Value::Boolean(true), Value::Boolean(true), Value::Boolean(true), Value::Boolean(false)]));
+    assert_eq!(interpreter.execute("(with-input-from-string \"one\\ntwo\" (lambda () (list (read-line) (read-char))))").unwrap(),
+               Value::List(vec![Value::String("one".to_string()), Value::Char('t')]));
+    assert_eq!(format!("{:?}", interpreter.execute("(list #\\a #\\space #\\newline #\\x7 (eof-object))").unwrap()),
+               "(#\\a #\\space #\\newline #\\alarm #<eof>)");
+    assert_eq!(interpreter.execute("(close-port p) (read-char p)").err().unwrap(),
+               "RuntimeError: Can't read from a closed port: string");
+}
+
+#[test]
+fn test_interpreter_read() {
+    let interpreter = Interpreter::new();
+    interpreter.execute("(define p (open-input-string \"(a [b]) 42 \\\"s\\\" ; comment\\n #;x 'q\\n#| done |#  \"))").unwrap();
+    assert_eq!(format!("{:?}", interpreter.execute("(list (read p) (read p) (read p) (read p) (read p) (read p))").unwrap()),
+               "((a (b)) 42 \"s\" (quote q) #<eof> #<eof>)");
+    assert_eq!(interpreter.execute("(define q (open-input-string \"(a)(b)c\")) (list (read q) (peek-char q) (read q) (read q))").unwrap(),
+               Value::List(vec![Value::List(vec![Value::Symbol("a".to_string())]), Value::Char('('),
+                                Value::List(vec![Value::Symbol("b".to_string())]), Value::Symbol("c".to_string())]));
+    assert_eq!(interpreter.execute("(read-from-string \"(+ 1 2) ignored\")").unwrap(),
+               Value::List(vec![Value::Symbol("+".to_string()), Value::Integer(1), Value::Integer(2)]));
+    assert_eq!(interpreter.execute("(eval (string->datum \"(+ 1 2)\"))").unwrap(),
+               Value::Integer(3));
+    assert_eq!(format!("{:?}", interpreter.execute("(list (read-from-string \"#u8(1 2)\") (read (open-input-string \" #u8() x\")))").unwrap()),
+               "(#u8(1 2) #u8())");
+
+    assert_eq!(interpreter.execute("(define r (open-input-string \"(a)\\n  (b #t1)\")) (read r) (read r)").err().unwrap(),
+               "RuntimeError: Error reading from port string: SyntaxError: Unexpected character when looking for a delimiter: 1 (line: 2, column: 8)");
+    assert_eq!(interpreter.execute("(read-from-string \"(a \\\"b\")").err().unwrap(),
+               "RuntimeError: Error reading from port string: SyntaxError: Expected end quote, but found EOF instead (line: 1, column: 6)");
+    assert_eq!(interpreter.execute("(read-from-string \"(a b\")").err().unwrap(),
+               "RuntimeError: Error reading from port string: ParseError: Unexpected end of input, depth: 1 (line: 1, column: 1)");
+    assert_eq!(interpreter.execute("(read-from-string \" ; note\n  #| block |# (a [b] \\\"|\\\" #\\\\) \")").err().unwrap(),
+               "RuntimeError: Error reading from port string: ParseError: Unexpected end of input, depth: 1 (line: 2, column: 15)");
+    assert_eq!(format!("{:?}", interpreter.execute("(read-from-string \"(a \\\")\\\" |b)| #\\\\( #| ) |# c)\")").unwrap()),
+               "(a \")\" |b)| #\\( c)");
+}
+
+#[test]
+fn test_interpreter_bytevectors() {
+    let interpreter = Interpreter::new();
+    assert_eq!(format!("{:?}", interpreter.execute("(define b (make-bytevector 3 7)) (bytevector-u8-set! b 1 255) b").unwrap()),
+               "#u8(7 255 7)");
+    assert_eq!(interpreter.execute("(list (bytevector-length b) (bytevector-u8-ref b 1) (bytevector? b) (equal? #u8(1 2) (bytevector 1 2)))").unwrap(),
+               Value::List(vec![Value::Integer(3), Value::Integer(255), Value::Boolean(true), Value::Boolean(true)]));
+    assert_eq!(format!("{:?}", interpreter.execute("(bytevector-append (bytevector-copy b 1) #u8() (bytevector-copy #u8(1 2 3) 0 1))").unwrap()),
+               "#u8(255 7 1)");
+    assert_eq!(interpreter.execute("(utf8->string (string->utf8 \"λx\"))").unwrap(),
+               Value::String("λx".to_string()));
+    assert_eq!(format!("{:?}", interpreter.execute("(string->utf8 \"λ\")").unwrap()),
+               "#u8(206 187)");
+    assert_eq!(interpreter.execute("(make-bytevector 100000000000000)").err().unwrap(),
+               "RuntimeError: Length 100000000000000 is too large for make-bytevector");
+    assert_eq!(interpreter.execute("(bytevector-u8-set! b 0 256)").err().unwrap(),
+               "RuntimeError: Must supply a byte to bytevector-u8-set!: 256");
+    assert_eq!(interpreter.execute("(bytevector-copy b 2 1)").err().unwrap(),
+               "RuntimeError: Range out of bounds in bytevector-copy: 2 to 1");
+}
+
+#[test]
+fn test_interpreter_binary_ports() {
+    let interpreter = Interpreter::new();
+    interpreter.execute("(define in (open-input-bytevector #u8(137 80 78 71 13 10)))").unwrap();
+    assert_eq!(format!("{:?}", interpreter.execute("(list (peek-u8 in) (read-u8 in) (read-bytevector 3 in) (read-bytevector 9 in) (read-u8 in) (read-bytevector 1 in))").unwrap()),
+               "(137 137 #u8(80 78 71) #u8(13 10) #<eof> #<eof>)");
+    assert_eq!(format!("{:?}", interpreter.execute("(define out (open-output-bytevector)) (write-u8 1 out) (write-bytevector #u8(2 3 4) out 1) (get-output-bytevector out)").unwrap()),
+               "#u8(1 3 4)");
+
+    let path = ::std::env::temp_dir().join(format!("rusty_scheme_binary_{}", ::std::process::id()));
+    interpreter.execute(&format!("(define path {:?})", path.to_str().unwrap())).unwrap();
+    assert_eq!(format!("{:?}", interpreter.execute("(define f (open-binary-output-file path)) (write-bytevector #u8(0 255 10) f) (close-port f) (call-with-input-file path (lambda (p) (read-bytevector 10 p)))").unwrap()),
+               "#u8(0 255 10)");
+    ::std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_interpreter_format() {
+    let interpreter = Interpreter::new();
+    assert_eq!(interpreter.execute("(format \"~a and ~s, ~v~%~~\" \"str\" \"str\" 'sym)").unwrap(),
+               Value::String("str and \"str\", 'sym\n~".to_string()));
+    assert_eq!(interpreter.execute("(format \"[~6a|~6@a|~5d|~@d|~x|~8,'0b|~o]\" 'ab \"cd\" 42 7 -255 5 8)").unwrap(),
+               Value::String("[ab    |    cd|   42|+7|-ff|00000101|10]".to_string()));
+    assert_eq!(interpreter.execute("(with-output-to-string (lambda () (printf \"~a-~a\" 1 2)))").unwrap(),
+               Value::String("1-2".to_string()));
+    assert_eq!(interpreter.execute("(call-with-output-string (lambda (p) (fprintf p \"x=~s\" '(1 \"a\"))))").unwrap(),
+               Value::String("x=(1 \"a\")".to_string()));
+    assert_eq!(interpreter.execute("(format \"~a ~a\" 1)").err().unwrap(),
+               "RuntimeError: Not enough arguments for the format string given to format: \"~a ~a\"");
+    assert_eq!(interpreter.execute("(format \"~a\" 1 2)").err().unwrap(),
+               "RuntimeError: Too many arguments for the format string given to format: \"~a\"");
+    assert_eq!(interpreter.execute("(format \"~q\" 1)").err().unwrap(),
+               "RuntimeError: Unknown directive ~q in the format string given to format: \"~q\"");
+    assert_eq!(interpreter.execute("(format \"~x\" 'a)").err().unwrap(),
+               "RuntimeError: Must supply an integer for ~x in format: a");
+    assert_eq!(interpreter.execute("(format \"~9999999999999a\" 1)").err().unwrap(),
+               "RuntimeError: Width 9999999999999 is too large in the format string given to format: \"~9999999999999a\"");
+    assert_eq!(interpreter.execute("(format \"~99999999999999999999999a\" 1)").err().unwrap(),
+               "RuntimeError: Malformed width in the format string given to format: \"~99999999999999999999999a\"");
+}
+
+#[test]
+fn test_interpreter_variadic_arithmetic() {
+    let interpreter = Interpreter::new();
+    assert_eq!(interpreter.execute("(list (+) (*) (- 5) (- 10 1 2) (/ 2) (/ 12 2 3) (/ 7 2) (+ 1 2.5) (* 2 0.5))").unwrap(),
+               Value::List(vec![Value::Integer(0), Value::Integer(1), Value::Integer(-5), Value::Integer(7), Value::Rational(1, 2),
+                                Value::Integer(2), Value::Rational(7, 2), Value::Float(3.5), Value::Float(1.0)]));
+    assert_eq!(interpreter.execute("(list (< 1 2 3) (< 1 3 2) (<= 1 1 2) (>= 3 3 4) (> 3 2 1.5) (= 1 1.0 1) (< 1))").unwrap(),
+               Value::List(vec![Value::Boolean(true), Value::Boolean(false), Value::Boolean(true), Value::Boolean(false),
+                                Value::Boolean(true), Value::Boolean(true), Value::Boolean(true)]));
+    assert_eq!(interpreter.execute("(/ 1 0)").err().unwrap(),
+               "RuntimeError: Division by zero in /");
+    assert_eq!(interpreter.execute("(* 4611686018427387904 2)").err().unwrap(),
+               "RuntimeError: Integer overflow in *: 4611686018427387904 and 2");
+    assert_eq!(interpreter.execute("(/ (- -9223372036854775807 1) -1)").err().unwrap(),
+               "RuntimeError: Integer overflow in /: -9223372036854775808 and -1");
+    assert_eq!(interpreter.execute("(< 1 'a)").err().unwrap(),
+               "RuntimeError: Must supply a number to <: a");
+}
+
+#[test]
+fn test_interpreter_integer_division() {
+    let interpreter = Interpreter::new();
+    assert_eq!(format!("{:?}", interpreter.execute("(list (quotient -7 2) (remainder -7 2) (modulo -7 2) (modulo 7 -2) (floor/ -7 2) (truncate/ -7 2) (quotient 7.0 2))").unwrap()),
+               "(-3 -1 1 -1 (-4 1) (-3 -1) 3.0)");
+    assert_eq!(format!("{:?}", interpreter.execute("(list (abs -5) (abs -2.5) (min 3 1 2) (max 3 1.0) (gcd 12 -18) (gcd) (lcm 4 6) (lcm))").unwrap()),
+               "(5 2.5 1 3.0 6 0 12 1)");
+    assert_eq!(format!("{:?}", interpreter.execute("(list (expt 2 10) (expt 2 -1) (expt 1 -5) (expt 2.0 3) (expt 4 0.5) (exact-integer-sqrt 17))").unwrap()),
+               "(1024 1/2 1 8.0 2.0 (4 1))");
+    assert_eq!(format!("{:?}", interpreter.execute("(list (zero? 0.0) (positive? -1) (negative? -1) (odd? 3) (even? 4.0) (number? 'a) (integer? 2.0) (integer? 2.5))").unwrap()),
+               "(#t #f #t #t #t #f #t #f)");
+    assert_eq!(format!("{:?}", interpreter.execute("(list (gcd 1e300 3) (gcd 2e300 4e300) (lcm 1e300 2) (gcd (- -9223372036854775807 1) 2))").unwrap()),
+               "(3.0 2e300 1e300 2)");
+    assert_eq!(interpreter.execute("(gcd (- -9223372036854775807 1) 0)").err().unwrap(),
+               "RuntimeError: Integer overflow in gcd: -9223372036854775808");
+    assert_eq!(interpreter.execute("(lcm (- -9223372036854775807 1) 1)").err().unwrap(),
+               "RuntimeError: Integer overflow in lcm: 1 and -9223372036854775808");
+    assert_eq!(interpreter.execute("(modulo 1 0)").err().unwrap(),
+               "RuntimeError: Division by zero in modulo");
+    assert_eq!(format!("{:?}", interpreter.execute("(define min (- -9223372036854775807 1)) (list (remainder min -1) (modulo min -1) (modulo min 2))").unwrap()),
+               "(0 0 0)");
+    assert_eq!(interpreter.execute("(quotient min -1)").err().unwrap(),
+               "RuntimeError: Integer overflow in quotient: -9223372036854775808 and -1");
+    assert_eq!(interpreter.execute("(floor/ min -1)").err().unwrap(),
+               "RuntimeError: Integer overflow in floor/: -9223372036854775808 and -1");
+    assert_eq!(interpreter.execute("(odd? 1.5)").err().unwrap(),
+               "RuntimeError: Must supply an integer to odd?: 1.5");
+}
+
+#[test]
+fn test_interpreter_inexact_procedures() {
+    let interpreter = Interpreter::new();
+    assert_eq!(format!("{:?}", interpreter.execute("(list (sqrt 16) (sqrt 2.25) (sqrt 2) (exp 0) (log 1) (log 8 2) (atan 1 1) (sin 0))").unwrap()),
+               "(4 1.5 1.4142135623730951 1.0 0.0 3.0 0.7853981633974483 0.0)");
+    assert_eq!(format!("{:?}", interpreter.execute("(list (floor -2.5) (ceiling 2.1) (round 2.5) (round 3.5) (round -2.5) (round 2.6) (truncate -2.7) (round 7))").unwrap()),
+               "(-3.0 3.0 2.0 4.0 -2.0 3.0 -2.0 7)");
+    assert_eq!(format!("{:?}", interpreter.execute("(list (exact 3.0) (inexact 3) (square 5) (square 1.5))").unwrap()),
+               "(3 3.0 25 2.25)");
+    assert_eq!(format!("{:?}", interpreter.execute("(list (exact 1.5) (exact -0.125) (exact 0.1))").unwrap()),
+               "(3/2 -1/8 3602879701896397/36028797018963968)");
+    assert_eq!(interpreter.execute("(exact +inf.0)").err().unwrap(),
+               "RuntimeError: No exact equivalent for +inf.0");
+}
+
+#[test]
+fn test_interpreter_rationals() {
+    let interpreter = Interpreter::new();
+    assert_eq!(format!("{:?}", interpreter.execute("(list 1/2 -6/4 4/2 #e1.5 #e1e-3 #x1/A (+ 1/2 1/3) (- 1/2 1/2) (* 2/3 3/4) (/ 1/2 3) (+ 1/2 0.5))").unwrap()),
+               "(1/2 -3/2 2 3/2 1/1000 1/10 5/6 0 1/2 1/6 1.0)");
+    assert_eq!(format!("{:?}", interpreter.execute("(list (< 1/3 0.34 1/2) (= 1/2 0.5) (max 1/2 1/3) (abs -1/2) (numerator 6/4) (denominator 6/4) (denominator 0.5) (rational? 1/2) (integer? 1/2) (inexact 1/4))").unwrap()),
+               "(#t #t 1/2 1/2 3 2 2.0 #t #f 0.25)");
+    assert_eq!(format!("{:?}", interpreter.execute("(list (floor -7/2) (ceiling -7/2) (truncate -7/2) (round 5/2) (round 7/2) (round -5/2) (sqrt 9/4) (sqrt -1/4) (expt 2/3 2) (expt 2 -3) 1/2+1/3i)").unwrap()),
+               "(-4 -3 -3 2 4 -2 3/2 +1/2i 4/9 1/8 1/2+1/3i)");
+    assert_eq!(interpreter.execute("(list (eqv? 1/2 (/ 2 4)) (eqv? 1/2 0.5) (equal? '(1/2) (list (/ 1 2))))").unwrap(),
+               Value::List(vec![Value::Boolean(true), Value::Boolean(false), Value::Boolean(true)]));
+    assert_eq!(interpreter.execute("(+ 1/9223372036854775807 1/9223372036854775806)").err().unwrap(),
+               "RuntimeError: Integer overflow in +: 1/9223372036854775807 and 1/9223372036854775806");
+    assert_eq!(interpreter.execute("(/ 1/2 0)").err().unwrap(),
+               "RuntimeError: Division by zero in /");
+}
+
+#[test]
+fn test_interpreter_bitwise_procedures() {
+    let interpreter = Interpreter::new();
+    assert_eq!(format!("{:?}", interpreter.execute("(list (bitwise-and 12 10) (bitwise-or 12 10) (bitwise-xor 12 10) (bitwise-and) (bitwise-not 5))").unwrap()),
+               "(8 14 6 -1 -6)");
+    assert_eq!(format!("{:?}", interpreter.execute("(list (arithmetic-shift 1 10) (arithmetic-shift 1024 -3) (arithmetic-shift -5 -1) (arithmetic-shift -1 -100))").unwrap()),
+               "(1024 128 -3 -1)");
+    assert_eq!(format!("{:?}", interpreter.execute("(list (bit-count 13) (bit-count -1) (integer-length 255) (integer-length -256) (integer-length 0))").unwrap()),
+               "(3 0 8 8 0)");
+    assert_eq!(format!("{:?}", interpreter.execute("(list (bit-set? 1 5) (bit-set? 0 5) (bit-set? 100 -1) (copy-bit 1 5 #t) (copy-bit 0 5 #f) (bit-field 214 2 6) (copy-bit 70 -1 #t))").unwrap()),
+               "(#f #t #t 7 4 5 -1)");
+    assert_eq!(interpreter.execute("(arithmetic-shift 1 63)").err().unwrap(),
+               "RuntimeError: Integer overflow in arithmetic-shift: 1 and 63");
+    assert_eq!(interpreter.execute("(bitwise-and 1 1.0)").err().unwrap(),
+               "RuntimeError: Must supply an exact integer to bitwise-and: 1.0");
+}
+
+#[test]
+fn test_interpreter_complex_numbers() {
+    let interpreter = Interpreter::new();
+    assert_eq!(format!("{:?}", interpreter.execute("(list 3+4i 1.5-2.0i +i -2i 1@0 (+ 1+2i 3-2i) (* 1+2i 3+4i) (/ 10+5i 1+2i) (- 2+i))").unwrap()),
+               "(3+4i 1.5-2.0i +i -2i 1 4 -5+10i 4-3i -2-i)");
+    assert_eq!(format!("{:?}", interpreter.execute("(list (make-rectangular 1 2) (real-part 3+4i) (imag-part 3+4i) (imag-part 5) (magnitude 3+4i) (magnitude -5) (angle 1) (angle +i))").unwrap()),
+               "(1+2i 3 4 0 5 5 0 1.5707963267948966)");
+    assert_eq!(format!("{:?}", interpreter.execute("(list (sqrt -4) (sqrt -2.0) (sqrt -3+4i) (= 1+2i 1.0+2.0i) (number? 1+i) (real? 1+i) (zero? 0+0i))").unwrap()),
+               "(+2i +1.4142135623730951i 1.0+2.0i #t #t #f #t)");
+    assert_eq!(format!("{:?}", interpreter.execute("(list (make-polar 2 0) (exact 1.0+2.0i) (inexact 1+2i) (square +i))").unwrap()),
+               "(2 1+2i 1.0+2.0i -1)");
+    assert_eq!(interpreter.execute("(< 1+i 2)").err().unwrap(),
+               "RuntimeError: Must supply a real number to <: 1+i");
+    assert_eq!(format!("{:?}", interpreter.execute("(list (expt -4 0.5) (expt +i 2) (expt 1+i 3) (expt 1.0+i 0.5) (expt -8 1) (expt 4 0.5))").unwrap()),
+               "(1.2246467991473532e-16+2.0i -1 -2+2i 1.0986841134678098+0.45508986056222733i -8 2.0)");
+    assert_eq!(format!("{:?}", interpreter.execute("(list (asin 2) (asin -2) (acos 2) (asin 0.5) (sin +i) (cos +i) (tan 1+i) (atan 2+i))").unwrap()),
+               "(1.5707963267948966-1.3169578969248166i -1.5707963267948966+1.3169578969248164i 0.0+1.3169578969248166i 0.5235987755982989 0.0+1.1752011936438014i 1.5430806348152437 0.27175258531951174+1.0839233273386948i 1.1780972450961724+0.1732867951399864i)");
+}
@@ -1,28 +1,120 @@
 use std::str;
 use std::fmt;
 use std::iter;
+use std::f64;
 
 pub fn tokenize(s: &str) -> Result<Vec<Token>, SyntaxError> {
-    Lexer::tokenize(s)
+    Lexer::tokenize(s, false)
+}
+
+// Same as tokenize, but starts out folding identifiers to lower case when fold_case is set
+pub fn tokenize_with_case_folding(s: &str, fold_case: bool) -> Result<Vec<Token>, SyntaxError> {
+    Lexer::tokenize(s, fold_case)
+}
+
+// Whether a single lexeme, like 42, +i or +inf.0, would be read as a number
+pub fn is_numeric(lexeme: &str) -> bool {
+    parse_numeric(lexeme).is_ok()
 }
 
 #[derive(PartialEq, Debug)]
 pub enum Token {
     OpenParen,
+    VectorOpen,
+    BytevectorOpen,
     CloseParen,
     Quote,
     Quasiquote,
     Unquote,
+    DatumComment,
+    DatumLabel(u64),
+    DatumReference(u64),
     Identifier(String),
     Integer(i64),
+    // a fraction in lowest terms, like 1/2
+    Rational(i64, i64),
+    Float(f64),
+    // the real and imaginary parts of a number like 3+4i or 1@2
+    Complex(Number, Number),
     Boolean(bool),
+    Char(char),
     String(String),
 }
 
+// A real number: an exact integer, an exact fraction in lowest terms with a denominator above 1, or a float
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Number {
+    Exact(i64),
+    Rational(i64, i64),
+    Inexact(f64),
+}
+
+impl Number {
+    pub fn to_f64(self) -> f64 {
+        match self {
+            Number::Exact(i) => i as f64,
+            Number::Rational(n, d) => n as f64 / d as f64,
+            Number::Inexact(f) => f
+        }
+    }
+
+    // The exact number n/d in lowest terms, or None if it doesn't fit in an i64 fraction.  Working in i128
+    // leaves room for the products of i64s, so arithmetic only overflows when its result does
+    pub fn rational(n: i128, d: i128) -> Option<Number> {
+        if d == 0 {
+            return None;
+        }
+        let (mut a, mut b) = (n.abs(), d.abs());
+        while b != 0 {
+            let r = a % b;
+            a = b;
+            b = r;
+        }
+        let (n, d) = if d < 0 { (-n / a, -d / a) } else { (n / a, d / a) };
+        if n < i64::MIN as i128 || n > i64::MAX as i128 || d > i64::MAX as i128 {
+            None
+        } else if d == 1 {
+            Some(Number::Exact(n as i64))
+        } else {
+            Some(Number::Rational(n as i64, d as i64))
+        }
+    }
+
+    // The exact value of a float, which is always a fraction with a power of two denominator
+    pub fn exact_from_f64(f: f64) -> Option<Number> {
+        if !f.is_finite() || f.abs() >= 1e19 {
+            return None;
+        }
+        let (mut n, mut d) = (f, 1i128);
+        while n.fract() != 0.0 {
+            if d > i64::MAX as i128 {
+                return None;
+            }
+            n *= 2.0;
+            d *= 2;
+        }
+        Number::rational(n as i128, d)
+    }
+}
+
 pub struct SyntaxError {
     message: String,
     line: u32,
     column: u32,
+    // whether the error came from running out of input, so more input might fix it
+    eof: bool,
+}
+
+impl SyntaxError {
+    pub fn at_eof(&self) -> bool {
+        self.eof
+    }
+
+    // Shift the position by where the input started, for input that was taken from partway through a file
+    pub fn offset(self, line: u32, column: u32) -> SyntaxError {
+        let (new_line, new_column) = if self.line == 1 { (line, column + self.column) } else { (line + self.line - 1, self.column) };
+        SyntaxError { message: self.message, line: new_line, column: new_column, eof: self.eof }
+    }
 }
 
 impl fmt::Display for SyntaxError {
@@ -38,21 +130,178 @@ impl fmt::Debug for SyntaxError {
 
 macro_rules! syntax_error {
     ($lexer:ident, $($arg:tt)*) => (
-        return Err(SyntaxError { message: format!($($arg)*), line: $lexer.line, column: $lexer.column })
+        return Err(SyntaxError { message: format!($($arg)*), line: $lexer.line, column: $lexer.column, eof: $lexer.current().is_none() })
     )
 }
 
+// Turns a complete numeric lexeme, including any #x/#b/#o/#d radix and #e/#i exactness prefixes, into a token
+fn parse_numeric(lexeme: &str) -> Result<Token, String> {
+    let mut radix = None;
+    let mut exactness = None;
+    let mut rest = lexeme;
+    while rest.starts_with('#') {
+        match rest[1..].chars().next().map(|c| c.to_ascii_lowercase()) {
+            Some('x') if radix.is_none() => radix = Some(16),
+            Some('b') if radix.is_none() => radix = Some(2),
+            Some('o') if radix.is_none() => radix = Some(8),
+            Some('d') if radix.is_none() => radix = Some(10),
+            Some('e') if exactness.is_none() => exactness = Some('e'),
+            Some('i') if exactness.is_none() => exactness = Some('i'),
+            _ => return Err(format!("Malformed number: {}", lexeme))
+        }
+        rest = &rest[2..];
+    }
+    let radix = radix.unwrap_or(10);
+    let exact = exactness == Some('e');
+    let malformed = || format!("Malformed number: {}", lexeme);
+
+    let (real, imag) = if let Some(at) = rest.find('@') {
+        // polar form, magnitude@angle
+        let magnitude = try!(parse_real(&rest[..at], radix, exact).ok_or_else(&malformed));
+        let angle = try!(parse_real(&rest[at + 1..], radix, exact).ok_or_else(&malformed));
+        match (magnitude, angle) {
+            (_, Number::Exact(0)) => (magnitude, angle),
+            _ => {
+                let (m, a) = (magnitude.to_f64(), angle.to_f64());
+                (Number::Inexact(m * a.cos()), Number::Inexact(m * a.sin()))
+            }
+        }
+    } else if rest.ends_with('i') || rest.ends_with('I') {
+        // rectangular form; the imaginary part starts at the last sign that doesn't lead the number or an exponent
+        let body = &rest[..rest.len() - 1];
+        let split = body.char_indices().rev()
+            .find(|&(i, c)| (c == '+' || c == '-') && i > 0 && !(radix == 10 && body[..i].ends_with(&['e', 'E'][..])))
+            .map(|(i, _)| i);
+        let (re, im) = match split {
+            Some(i) => (&body[..i], &body[i..]),
+            None => ("", body)
+        };
+        let imag = match im {
+            "+" => Number::Exact(1),
+            "-" => Number::Exact(-1),
+            _ if im.starts_with('+') || im.starts_with('-') => try!(parse_real(im, radix, exact).ok_or_else(&malformed)),
+            _ => return Err(malformed())
+        };
+        let real = if re.is_empty() { Number::Exact(0) } else { try!(parse_real(re, radix, exact).ok_or_else(&malformed)) };
+        (real, imag)
+    } else {
+        (try!(parse_real(rest, radix, exact).ok_or_else(&malformed)), Number::Exact(0))
+    };
+
+    match (try!(with_exactness(real, exactness, lexeme)), imag) {
+        (Number::Exact(val), Number::Exact(0)) => Ok(Token::Integer(val)),
+        (Number::Rational(n, d), Number::Exact(0)) => Ok(Token::Rational(n, d)),
+        (Number::Inexact(val), Number::Exact(0)) => Ok(Token::Float(val)),
+        (real, imag) => Ok(Token::Complex(real, try!(with_exactness(imag, exactness, lexeme))))
+    }
+}
+
+// Parses a real number without any prefixes; exact decimals, from #e, are read digit for digit rather than
+// through a float, so #e0.1 is 1/10
+fn parse_real(s: &str, radix: u32, exact: bool) -> Option<Number> {
+    let unsigned = if s.starts_with('+') || s.starts_with('-') { &s[1..] } else { s };
+    match s {
+        "+inf.0" => Some(Number::Inexact(f64::INFINITY)),
+        "-inf.0" => Some(Number::Inexact(f64::NEG_INFINITY)),
+        "+nan.0" | "-nan.0" => Some(Number::Inexact(f64::NAN)),
+        _ if radix == 10 && unsigned.contains(|c| c == '.' || c == 'e' || c == 'E') => {
+            // only allow the characters of a decimal, since rust also accepts things like "inf"
+            let valid = unsigned.chars().all(|c| c.is_digit(10) || c == '.' || c == 'e' || c == 'E' || c == '+' || c == '-');
+            if !valid {
+                return None;
+            }
+            if exact {
+                return parse_exact_decimal(unsigned).and_then(|(n, d)| Number::rational(if s.starts_with('-') { -n } else { n }, d));
+            }
+            match unsigned.parse::<f64>() {
+                Ok(val) => Some(Number::Inexact(if s.starts_with('-') { -val } else { val })),
+                _ => None
+            }
+        },
+        _ if s.contains('/') => {
+            let slash = s.find('/').unwrap();
+            let (n, d) = (&s[..slash], &s[slash + 1..]);
+            if d.starts_with('+') || d.starts_with('-') {
+                return None;
+            }
+            match (i64::from_str_radix(n, radix), i64::from_str_radix(d, radix)) {
+                (Ok(n), Ok(d)) => Number::rational(n as i128, d as i128),
+                _ => None
+            }
+        },
+        _ => i64::from_str_radix(s, radix).ok().map(Number::Exact)
+    }
+}
+
+// The numerator and denominator of an unsigned decimal like 1.25 or 5e-3
+fn parse_exact_decimal(s: &str) -> Option<(i128, i128)> {
+    let (mantissa, exponent) = match s.find(|c| c == 'e' || c == 'E') {
+        Some(e) => match s[e + 1..].parse::<i64>() {
+            Ok(exponent) => (&s[..e], exponent),
+            Err(_) => return None
+        },
+        None => (s, 0)
+    };
+    let (whole, fraction) = match mantissa.find('.') {
+        Some(dot) => (&mantissa[..dot], &mantissa[dot + 1..]),
+        None => (mantissa, "")
+    };
+    let digits = format!("{}{}", whole, fraction);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(10)) {
+        return None;
+    }
+    // an i128 holds powers of ten up to 10^38
+    let scale = exponent.saturating_sub(fraction.len() as i64);
+    if scale.abs() > 38 {
+        return None;
+    }
+    let power = 10i128.pow(scale.abs() as u32);
+    match digits.parse::<i128>() {
+        Ok(n) if scale >= 0 => n.checked_mul(power).map(|n| (n, 1)),
+        Ok(n) => Some((n, power)),
+        Err(_) => None
+    }
+}
+
+fn with_exactness(n: Number, exactness: Option<char>, lexeme: &str) -> Result<Number, String> {
+    match (exactness, n) {
+        (Some('i'), Number::Exact(_)) | (Some('i'), Number::Rational(_, _)) => Ok(Number::Inexact(n.to_f64())),
+        (Some('e'), Number::Inexact(val)) => match Number::exact_from_f64(val) {
+            Some(n) => Ok(n),
+            None => Err(format!("No exact equivalent for {}", lexeme))
+        },
+        (_, n) => Ok(n)
+    }
+}
+
+// The named characters, as in #\newline
+pub const CHAR_NAMES: &'static [(&'static str, char)] = &[
+    ("alarm", '\x07'), ("backspace", '\x08'), ("delete", '\x7f'), ("escape", '\x1b'), ("newline", '\n'),
+    ("null", '\0'), ("nul", '\0'), ("return", '\r'), ("space", ' '), ("tab", '\t'),
+];
+
+fn char_from_name(name: &str) -> Option<char> {
+    match CHAR_NAMES.iter().find(|&&(n, _)| n == name) {
+        Some(&(_, c)) => Some(c),
+        None if name.starts_with('x') => u32::from_str_radix(&name[1..], 16).ok().and_then(::std::char::from_u32),
+        None => None
+    }
+}
+
 struct Lexer<'a> {
     chars: iter::Peekable<str::Chars<'a>>,
     current: Option<char>,
     tokens: Vec<Token>,
     line: u32,
     column: u32,
+    fold_case: bool,
+    // the opening delimiters that haven't been closed yet, along with where they were found
+    openers: Vec<(char, u32, u32)>,
 }
 
 impl<'a> Lexer<'a> {
-    fn tokenize(s: &str) -> Result<Vec<Token>, SyntaxError> {
-        let mut lexer = Lexer { chars: s.chars().peekable(), current: None, tokens: Vec::new(), line: 1, column: 0 };
+    fn tokenize(s: &str, fold_case: bool) -> Result<Vec<Token>, SyntaxError> {
+        let mut lexer = Lexer { chars: s.chars().peekable(), current: None, tokens: Vec::new(), line: 1, column: 0, fold_case: fold_case, openers: Vec::new() };
         try!(lexer.run());
         Ok(lexer.tokens)
     }
@@ -103,13 +352,11 @@ impl<'a> Lexer<'a> {
                                 }
                             }
                         },
-                        '(' => {
-                            self.tokens.push(Token::OpenParen);
-                            self.advance();
+                        '(' | '[' | '{' => {
+                            self.open_paren(c);
                         },
-                        ')' => {
-                            self.tokens.push(Token::CloseParen);
-                            self.advance();
+                        ')' | ']' | '}' => {
+                            try!(self.close_paren(c));
                         },
                         '\'' => {
                             self.tokens.push(Token::Quote);
@@ -125,13 +372,22 @@ impl<'a> Lexer<'a> {
                         },
                         '+' | '-' => {
                             match self.peek() {
-                                Some('0'...'9') => {
-                                    // skip past the +/- symbol and parse the number
-                                    self.advance();
-                                    let val = try!(self.parse_number());
-                                    self.tokens.push(Token::Integer(if c == '-' { -1 * val } else { val }));
+                                Some('0'...'9') | Some('.') => {
+                                    let token = try!(self.parse_number());
+                                    self.tokens.push(token);
                                     try!(self.parse_delimiter());
                                 },
+                                Some('i') | Some('n') => {
+                                    // could be +inf.0, +nan.0 or an imaginary number like +i, otherwise it's an identifier
+                                    let val = try!(self.parse_identifier());
+                                    match parse_numeric(&val) {
+                                        Ok(token) => self.tokens.push(token),
+                                        Err(_) => {
+                                            let val = if self.fold_case { val.to_lowercase() } else { val };
+                                            self.tokens.push(Token::Identifier(val));
+                                        }
+                                    }
+                                },
                                 _ => {
                                     // not followed by a digit, must be an identifier
                                     self.tokens.push(Token::Identifier(c.to_string()));
@@ -141,14 +397,78 @@ impl<'a> Lexer<'a> {
                             }
                         },
                         '#' => {
-                            let val = try!(self.parse_boolean());
-                            self.tokens.push(Token::Boolean(val));
-                            try!(self.parse_delimiter());
+                            match self.peek() {
+                                Some('|') => {
+                                    try!(self.skip_block_comment());
+                                },
+                                Some(';') => {
+                                    // datum comment, the parser skips the datum that follows
+                                    self.tokens.push(Token::DatumComment);
+                                    self.advance();
+                                    self.advance();
+                                },
+                                Some('!') => {
+                                    try!(self.parse_directive());
+                                },
+                                Some('0'...'9') => {
+                                    try!(self.parse_datum_label());
+                                },
+                                Some('(') => {
+                                    // vector literal, #( ... )
+                                    self.advance();
+                                    self.openers.push(('(', self.line, self.column));
+                                    self.tokens.push(Token::VectorOpen);
+                                    self.advance();
+                                },
+                                Some('u') => {
+                                    // bytevector literal, #u8( ... )
+                                    self.advance();
+                                    self.advance();
+                                    let truncated = match (self.current(), self.peek()) {
+                                        (None, _) | (Some('8'), None) => true,
+                                        _ => false
+                                    };
+                                    if truncated {
+                                        // the rest of the prefix might still be coming, so this counts as running out of input
+                                        return Err(SyntaxError { message: "Expected #u8( to start a bytevector, but found EOF instead".to_string(),
+                                                                 line: self.line, column: self.column, eof: true });
+                                    }
+                                    if self.current() != Some('8') || self.peek() != Some('(') {
+                                        syntax_error!(self, "Expected #u8( to start a bytevector");
+                                    }
+                                    self.advance();
+                                    self.openers.push(('(', self.line, self.column));
+                                    self.tokens.push(Token::BytevectorOpen);
+                                    self.advance();
+                                },
+                                Some('\\') => {
+                                    let val = try!(self.parse_char());
+                                    self.tokens.push(Token::Char(val));
+                                    try!(self.parse_delimiter());
+                                },
+                                Some('x') | Some('X') | Some('b') | Some('B') | Some('o') | Some('O') |
+                                Some('d') | Some('D') | Some('e') | Some('E') | Some('i') | Some('I') => {
+                                    // radix or exactness prefix
+                                    let token = try!(self.parse_number());
+                                    self.tokens.push(token);
+                                    try!(self.parse_delimiter());
+                                },
+                                _ => {
+                                    let val = try!(self.parse_boolean());
+                                    self.tokens.push(Token::Boolean(val));
+                                    try!(self.parse_delimiter());
+                                }
+                            }
                         },
                         '0'...'9' => {
                             // don't advance -- let parse_number advance as needed
-                            let val = try!(self.parse_number());
-                            self.tokens.push(Token::Integer(val));
+                            let token = try!(self.parse_number());
+                            self.tokens.push(token);
+                            try!(self.parse_delimiter());
+                        },
+                        '.' if self.peek().map_or(false, |p| p.is_digit(10)) => {
+                            let token = try!(self.parse_number());
+                            self.tokens.push(token);
                             try!(self.parse_delimiter());
                         },
                         '\"' => {
@@ -156,11 +476,18 @@ impl<'a> Lexer<'a> {
                             self.tokens.push(Token::String(val));
                             try!(self.parse_delimiter());
                         },
-                        '[' | ']' | '{' | '}' | '|' | '\\' => {
+                        '|' => {
+                            // pipe quoted identifiers are taken as-is, without case folding
+                            let val = try!(self.parse_pipe_identifier());
+                            self.tokens.push(Token::Identifier(val));
+                            try!(self.parse_delimiter());
+                        },
+                        '\\' => {
                             syntax_error!(self, "Unexpected character: {}", c);
                         },
                         _ => {
                             let val = try!(self.parse_identifier());
+                            let val = if self.fold_case { val.to_lowercase() } else { val };
                             self.tokens.push(Token::Identifier(val));
                             try!(self.parse_delimiter());
                         }
@@ -172,25 +499,32 @@ impl<'a> Lexer<'a> {
         Ok(())
     }
 
-    fn parse_number(&mut self) -> Result<i64, SyntaxError> {
+    fn parse_number(&mut self) -> Result<Token, SyntaxError> {
+        // gather up the whole lexeme first, so malformed numbers can be reported in full
+        let (line, column) = (self.line, self.column);
         let mut s = String::new();
         loop {
             match self.current() {
-                Some(c) => {
-                    match c {
-                        '0'...'9' => {
+                Some(c) if c.is_alphanumeric() || c == '.' || c == '#' || c == '/' => {
+                    s.push(c);
+                    self.advance();
+                },
+                Some(c) if c == '+' || c == '-' || c == '@' => {
+                    // signs and @ carry on into exponents and the parts of complex numbers, as in 1e+5, 3-4i and 1@-2
+                    match self.peek() {
+                        Some(p) if p.is_alphanumeric() || p == '.' || (c == '@' && (p == '+' || p == '-')) => {
                             s.push(c);
                             self.advance();
                         },
                         _ => break
                     }
                 },
-                None => break
+                _ => break
             }
         }
-        match s.parse() {
-            Ok(value) => Ok(value),
-            Err(_) => { syntax_error!(self, "Not a number: {}", self.current().unwrap()); },
+        match parse_numeric(&s) {
+            Ok(token) => Ok(token),
+            Err(message) => Err(SyntaxError { message: message, line: line, column: column, eof: false })
         }
     }
 
@@ -207,9 +541,111 @@ impl<'a> Lexer<'a> {
                 self.advance();
                 Ok(false)
             },
-            _ => {
-                syntax_error!(self, "Unexpected character when looking for t/f: {}", self.current().unwrap())
+            Some(c) => {
+                syntax_error!(self, "Unexpected character when looking for t/f: {}", c)
+            },
+            None => syntax_error!(self, "Expected t/f, but found EOF instead")
+        }
+    }
+
+    fn skip_block_comment(&mut self) -> Result<(), SyntaxError> {
+        // block comments can be nested, so keep track of how deep we are
+        let mut depth = 0;
+        loop {
+            match (self.current(), self.peek()) {
+                (Some('#'), Some('|')) => {
+                    depth += 1;
+                    self.advance();
+                    self.advance();
+                },
+                (Some('|'), Some('#')) => {
+                    depth -= 1;
+                    self.advance();
+                    self.advance();
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                },
+                (Some(_), _) => {
+                    self.advance();
+                },
+                (None, _) => syntax_error!(self, "Expected end of block comment, but found EOF instead")
+            }
+        }
+    }
+
+    fn parse_directive(&mut self) -> Result<(), SyntaxError> {
+        // a #! at the very start of the input is an interpreter line, like #!/usr/bin/env rusty_scheme
+        let at_start = self.line == 1 && self.column == 1;
+        self.advance();
+        self.advance();
+        let name = try!(self.parse_identifier());
+        match name.as_ref() {
+            "fold-case" => self.fold_case = true,
+            "no-fold-case" => self.fold_case = false,
+            _ if at_start => {
+                loop {
+                    match self.current() {
+                        Some('\n') | None => break,
+                        Some(_) => self.advance()
+                    }
+                }
+                return Ok(());
+            },
+            _ => syntax_error!(self, "Unknown directive: #!{}", name)
+        }
+        self.parse_delimiter()
+    }
+
+    fn parse_char(&mut self) -> Result<char, SyntaxError> {
+        // skip past the #\ and take the next character, whatever it is
+        self.advance();
+        self.advance();
+        let c = match self.current() {
+            Some(c) => c,
+            None => syntax_error!(self, "Expected a character after #\\, but found EOF instead")
+        };
+        self.advance();
+
+        // anything directly after it makes this a named character instead, like #\newline
+        let rest = try!(self.parse_identifier());
+        if rest.is_empty() {
+            return Ok(c);
+        }
+        let name = format!("{}{}", c, rest);
+        match char_from_name(&name) {
+            Some(c) => Ok(c),
+            None => syntax_error!(self, "Unknown character name: #\\{}", name)
+        }
+    }
+
+    fn parse_datum_label(&mut self) -> Result<(), SyntaxError> {
+        // #n= labels the datum that follows, and #n# refers back to it
+        self.advance();
+        let mut digits = String::new();
+        while let Some(c) = self.current() {
+            if !c.is_digit(10) {
+                break;
             }
+            digits.push(c);
+            self.advance();
+        }
+        let label = match digits.parse() {
+            Ok(label) => label,
+            Err(_) => syntax_error!(self, "Malformed datum label: #{}", digits)
+        };
+        match self.current() {
+            Some('=') => {
+                self.tokens.push(Token::DatumLabel(label));
+                self.advance();
+                Ok(())
+            },
+            Some('#') => {
+                self.tokens.push(Token::DatumReference(label));
+                self.advance();
+                self.parse_delimiter()
+            },
+            _ => syntax_error!(self, "Malformed datum label: #{}", digits)
         }
     }
 
@@ -237,6 +673,67 @@ impl<'a> Lexer<'a> {
         Ok(s)
     }
 
+    fn parse_pipe_identifier(&mut self) -> Result<String, SyntaxError> {
+        self.advance();
+
+        let mut s = String::new();
+        loop {
+            match self.current() {
+                Some('|') => {
+                    self.advance();
+                    break;
+                },
+                Some('\\') => {
+                    let c = try!(self.parse_escape("identifier"));
+                    s.push(c);
+                },
+                Some(c) => {
+                    s.push(c);
+                    self.advance();
+                },
+                None => syntax_error!(self, "Expected closing |, but found EOF instead")
+            }
+        }
+        Ok(s)
+    }
+
+    // Reads the escape sequence after a backslash, inside a string or pipe quoted identifier
+    fn parse_escape(&mut self, context: &str) -> Result<char, SyntaxError> {
+        self.advance();
+        let c = match self.current() {
+            Some('|') => '|',
+            Some('\"') => '\"',
+            Some('\\') => '\\',
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('a') => '\x07',
+            Some('x') => {
+                // hex scalar value, terminated by a semicolon
+                let mut hex = String::new();
+                self.advance();
+                loop {
+                    match self.current() {
+                        Some(';') => break,
+                        Some(c) if c.is_digit(16) => {
+                            hex.push(c);
+                            self.advance();
+                        },
+                        _ => syntax_error!(self, "Malformed hex escape in {}: \\x{}", context, hex)
+                    }
+                }
+                match u32::from_str_radix(&hex, 16).ok().and_then(::std::char::from_u32) {
+                    Some(c) => c,
+                    None => syntax_error!(self, "Malformed hex escape in {}: \\x{};", context, hex)
+                }
+            },
+            Some(c) => syntax_error!(self, "Unknown escape in {}: \\{}", context, c),
+            None => syntax_error!(self, "Expected an escape in {}, but found EOF instead", context)
+        };
+        self.advance();
+        Ok(c)
+    }
+
     fn parse_string(&mut self) -> Result<String, SyntaxError> {
         if self.current() != Some('\"') { syntax_error!(self, "Unexpected character: {}", self.current().unwrap()) };
         self.advance();
@@ -250,6 +747,10 @@ impl<'a> Lexer<'a> {
                             self.advance();
                             break;
                         },
+                        '\\' => {
+                            let c = try!(self.parse_escape("string"));
+                            s.push(c);
+                        },
                         _ => {
                             s.push(c);
                             self.advance();
@@ -262,15 +763,39 @@ impl<'a> Lexer<'a> {
         Ok(s)
     }
 
+    fn open_paren(&mut self, c: char) {
+        // brackets and braces are interchangeable with parens, as long as they're closed by their partner
+        self.openers.push((c, self.line, self.column));
+        self.tokens.push(Token::OpenParen);
+        self.advance();
+    }
+
+    fn close_paren(&mut self, c: char) -> Result<(), SyntaxError> {
+        match self.openers.pop() {
+            Some((opener, line, column)) => {
+                let expected = match opener {
+                    '[' => ']',
+                    '{' => '}',
+                    _ => ')'
+                };
+                if c != expected {
+                    syntax_error!(self, "Expected {} to close {} from line {}, column {}, but found {} instead", expected, opener, line, column, c);
+                }
+            },
+            // unbalanced closers are left for the parser to complain about
+            None => ()
+        }
+        self.tokens.push(Token::CloseParen);
+        self.advance();
+        Ok(())
+    }
+
     fn parse_delimiter(&mut self) -> Result<(), SyntaxError> {
         match self.current() {
             Some(c) => {
                 match c {
                     _ if c.is_whitespace() => (),
-                    ')' => {
-                        self.tokens.push(Token::CloseParen);
-                        self.advance();
-                    },
+                    ')' | ']' | '}' => try!(self.close_paren(c)),
                     _ => syntax_error!(self, "Unexpected character when looking for a delimiter: {}", c),
                 }
             },
@@ -326,6 +851,8 @@ fn test_lexer_strings() {
                vec![Token::String("hello".to_string())]);
     assert_eq!(tokenize("\"a _ $ snthoeau(*&G#$()*^!\"").unwrap(),
                vec![Token::String("a _ $ snthoeau(*&G#$()*^!".to_string())]);
+    assert_eq!(tokenize("\"a \\\"quoted\\\" \\\\ \\x3bb;\\n\"").unwrap(),
+               vec![Token::String("a \"quoted\" \\ \u{3bb}\n".to_string())]);
     assert_eq!(tokenize("\"truncated").err().unwrap().to_string(),
                "SyntaxError: Expected end quote, but found EOF instead (line: 1, column: 11)");
 }
@@ -339,8 +866,8 @@ fn test_lexer_whitespace() {
 
 #[test]
 fn test_lexer_bad_syntax() {
-    assert_eq!(tokenize("([)").err().unwrap().to_string(),
-               "SyntaxError: Unexpected character: [ (line: 1, column: 2)");
+    assert_eq!(tokenize("(\\)").err().unwrap().to_string(),
+               "SyntaxError: Unexpected character: \\ (line: 1, column: 2)");
 }
 
 #[test]
@@ -391,3 +918,135 @@ fn test_lexer_unicode_identifiers() {
     assert_eq!(tokenize("日本国").unwrap(),
                vec![Token::Identifier("日本国".to_string())]);
 }
+
+#[test]
+fn test_lexer_block_comments() {
+    assert_eq!(tokenize("(a #| comment |# b)").unwrap(),
+               vec![Token::OpenParen, Token::Identifier("a".to_string()), Token::Identifier("b".to_string()), Token::CloseParen]);
+    assert_eq!(tokenize("#| outer #| inner |# (still commented) |# c").unwrap(),
+               vec![Token::Identifier("c".to_string())]);
+    assert_eq!(tokenize("#| line 1\nline 2 |#\n42").unwrap(),
+               vec![Token::Integer(42)]);
+    assert_eq!(tokenize("a #| #| |# b").err().unwrap().to_string(),
+               "SyntaxError: Expected end of block comment, but found EOF instead (line: 1, column: 13)");
+}
+
+#[test]
+fn test_lexer_datum_comments() {
+    assert_eq!(tokenize("(a #;(b c) d)").unwrap(),
+               vec![Token::OpenParen, Token::Identifier("a".to_string()), Token::DatumComment, Token::OpenParen, Token::Identifier("b".to_string()), Token::Identifier("c".to_string()), Token::CloseParen, Token::Identifier("d".to_string()), Token::CloseParen]);
+}
+
+#[test]
+fn test_lexer_directives() {
+    assert_eq!(tokenize("#!/usr/bin/env rusty_scheme\n(Display 1)").unwrap(),
+               vec![Token::OpenParen, Token::Identifier("Display".to_string()), Token::Integer(1), Token::CloseParen]);
+    assert_eq!(tokenize("Abc #!fold-case Abc #!no-fold-case Abc").unwrap(),
+               vec![Token::Identifier("Abc".to_string()), Token::Identifier("abc".to_string()), Token::Identifier("Abc".to_string())]);
+    assert_eq!(tokenize("a\n#!/usr/bin/env").err().unwrap().to_string(),
+               "SyntaxError: Unknown directive: #!/usr/bin/env (line: 2, column: 15)");
+}
+
+#[test]
+fn test_lexer_brackets() {
+    assert_eq!(tokenize("[a {b}]").unwrap(),
+               vec![Token::OpenParen, Token::Identifier("a".to_string()), Token::OpenParen, Token::Identifier("b".to_string()), Token::CloseParen, Token::CloseParen]);
+    assert_eq!(tokenize("(let ([x 1]\n      [y 2)) x)").err().unwrap().to_string(),
+               "SyntaxError: Expected ] to close [ from line 2, column 7, but found ) instead (line: 2, column: 11)");
+    assert_eq!(tokenize("(a}").err().unwrap().to_string(),
+               "SyntaxError: Expected ) to close ( from line 1, column 1, but found } instead (line: 1, column: 3)");
+}
+
+#[test]
+fn test_lexer_numbers() {
+    assert_eq!(tokenize("#x1F #X-ff #b1010 #o17 #d42 #e#x10").unwrap(),
+               vec![Token::Integer(31), Token::Integer(-255), Token::Integer(10), Token::Integer(15), Token::Integer(42), Token::Integer(16)]);
+    assert_eq!(tokenize("1.5 -.25 1e3 2.5E-2 #i3 #e2.0").unwrap(),
+               vec![Token::Float(1.5), Token::Float(-0.25), Token::Float(1000.0), Token::Float(0.025), Token::Float(3.0), Token::Integer(2)]);
+    assert_eq!(tokenize("1/2 -6/4 #e1.5 #e-0.25 #e1e2 #i1/4 #x-1/A").unwrap(),
+               vec![Token::Rational(1, 2), Token::Rational(-3, 2), Token::Rational(3, 2), Token::Rational(-1, 4), Token::Integer(100),
+                    Token::Float(0.25), Token::Rational(-1, 10)]);
+    assert_eq!(tokenize("(+inf.0 -inf.0 -name)").unwrap(),
+               vec![Token::OpenParen, Token::Float(f64::INFINITY), Token::Float(f64::NEG_INFINITY), Token::Identifier("-name".to_string()), Token::CloseParen]);
+    match tokenize("+nan.0").unwrap()[0] {
+        Token::Float(val) => assert!(val.is_nan()),
+        _ => panic!("Expected NaN")
+    }
+}
+
+#[test]
+fn test_lexer_complex_numbers() {
+    assert_eq!(tokenize("3+4i -1.5-2e2i +i -i 2@0 #i1+i 1+0i").unwrap(),
+               vec![Token::Complex(Number::Exact(3), Number::Exact(4)), Token::Complex(Number::Inexact(-1.5), Number::Inexact(-200.0)),
+                    Token::Complex(Number::Exact(0), Number::Exact(1)), Token::Complex(Number::Exact(0), Number::Exact(-1)), Token::Integer(2),
+                    Token::Complex(Number::Inexact(1.0), Number::Inexact(1.0)), Token::Integer(1)]);
+    assert_eq!(tokenize("1@3.141592653589793").unwrap(),
+               vec![Token::Complex(Number::Inexact(-1.0), Number::Inexact(1.2246467991473532e-16))]);
+    assert_eq!(tokenize("+inf.0i -nai").unwrap(),
+               vec![Token::Complex(Number::Exact(0), Number::Inexact(f64::INFINITY)), Token::Identifier("-nai".to_string())]);
+    assert_eq!(tokenize("3+4").err().unwrap().to_string(),
+               "SyntaxError: Malformed number: 3+4 (line: 1, column: 1)");
+    assert_eq!(tokenize("1@2@3").err().unwrap().to_string(),
+               "SyntaxError: Malformed number: 1@2@3 (line: 1, column: 1)");
+}
+
+#[test]
+fn test_lexer_malformed_numbers() {
+    assert_eq!(tokenize("(+ 1\n   #x1G)").err().unwrap().to_string(),
+               "SyntaxError: Malformed number: #x1G (line: 2, column: 4)");
+    assert_eq!(tokenize("12abc").err().unwrap().to_string(),
+               "SyntaxError: Malformed number: 12abc (line: 1, column: 1)");
+    assert_eq!(tokenize("(1e)").err().unwrap().to_string(),
+               "SyntaxError: Malformed number: 1e (line: 1, column: 2)");
+    assert_eq!(tokenize("1/0").err().unwrap().to_string(),
+               "SyntaxError: Malformed number: 1/0 (line: 1, column: 1)");
+    assert_eq!(tokenize("#e+inf.0").err().unwrap().to_string(),
+               "SyntaxError: No exact equivalent for #e+inf.0 (line: 1, column: 1)");
+}
+
+#[test]
+fn test_lexer_pipe_identifiers() {
+    assert_eq!(tokenize("(|hello world| || |42| |a\\|b\\\\c| |\\x41;\\t|)").unwrap(),
+               vec![Token::OpenParen, Token::Identifier("hello world".to_string()), Token::Identifier("".to_string()), Token::Identifier("42".to_string()),
+                    Token::Identifier("a|b\\c".to_string()), Token::Identifier("A\t".to_string()), Token::CloseParen]);
+    assert_eq!(tokenize("#!fold-case |Hi| Hi").unwrap(),
+               vec![Token::Identifier("Hi".to_string()), Token::Identifier("hi".to_string())]);
+    assert_eq!(tokenize_with_case_folding("Hi #!no-fold-case Hi", true).unwrap(),
+               vec![Token::Identifier("hi".to_string()), Token::Identifier("Hi".to_string())]);
+    assert_eq!(tokenize("|abc").err().unwrap().to_string(),
+               "SyntaxError: Expected closing |, but found EOF instead (line: 1, column: 5)");
+    assert_eq!(tokenize("|a\\qb|").err().unwrap().to_string(),
+               "SyntaxError: Unknown escape in identifier: \\q (line: 1, column: 4)");
+}
+
+#[test]
+fn test_lexer_datum_labels() {
+    assert_eq!(tokenize("(#0=(a) #0# #12#)").unwrap(),
+               vec![Token::OpenParen, Token::DatumLabel(0), Token::OpenParen, Token::Identifier("a".to_string()), Token::CloseParen,
+                    Token::DatumReference(0), Token::DatumReference(12), Token::CloseParen]);
+    assert_eq!(tokenize("#1x").err().unwrap().to_string(),
+               "SyntaxError: Malformed datum label: #1 (line: 1, column: 3)");
+}
+
+#[test]
+fn test_lexer_chars() {
+    assert_eq!(tokenize("(#\\a #\\( #\\  #\\space #\\newline #\\x41 #\\λ)").unwrap(),
+               vec![Token::OpenParen, Token::Char('a'), Token::Char('('), Token::Char(' '), Token::Char(' '), Token::Char('\n'), Token::Char('A'),
+                    Token::Char('λ'), Token::CloseParen]);
+    assert_eq!(tokenize("#\\bogus").err().unwrap().to_string(),
+               "SyntaxError: Unknown character name: #\\bogus (line: 1, column: 8)");
+}
+
+#[test]
+fn test_lexer_bytevectors() {
+    assert_eq!(tokenize("#(1 #(a))").unwrap(),
+               vec![Token::VectorOpen, Token::Integer(1), Token::VectorOpen, Token::Identifier("a".to_string()), Token::CloseParen, Token::CloseParen]);
+    assert_eq!(tokenize("#u8(1 255)").unwrap(),
+               vec![Token::BytevectorOpen, Token::Integer(1), Token::Integer(255), Token::CloseParen]);
+    assert!(tokenize("#u8").err().unwrap().at_eof());
+    assert!(!tokenize("#u9(").err().unwrap().at_eof());
+    assert_eq!(tokenize("#u8(1]").err().unwrap().to_string(),
+               "SyntaxError: Expected ) to close ( from line 1, column 4, but found ] instead (line: 1, column: 6)");
+    assert_eq!(tokenize("#u16(1)").err().unwrap().to_string(),
+               "SyntaxError: Expected #u8( to start a bytevector (line: 1, column: 3)");
+}
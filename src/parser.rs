@@ -1,5 +1,6 @@
 use lexer::*;
 
+use std::collections::HashMap;
 use std::fmt;
 use std::slice;
 
@@ -7,13 +8,61 @@ pub fn parse(tokens: &Vec<Token>) -> Result<Vec<Node>, ParseError> {
     Parser::parse(tokens)
 }
 
+// Whether the tokens hold at least one whole datum, for reading one datum at a time from a port
+pub fn datum_complete(tokens: &[Token]) -> bool {
+    let mut depth = 0;
+    let mut comments = 0;
+    for token in tokens.iter() {
+        let ended = match *token {
+            Token::OpenParen | Token::VectorOpen | Token::BytevectorOpen => {
+                depth += 1;
+                false
+            },
+            Token::CloseParen => {
+                if depth == 0 {
+                    // unbalanced, so let the parser report it
+                    return true;
+                }
+                depth -= 1;
+                depth == 0
+            },
+            Token::Quote | Token::Quasiquote | Token::Unquote | Token::DatumLabel(_) => false,
+            Token::DatumComment => {
+                if depth == 0 {
+                    comments += 1;
+                }
+                false
+            },
+            _ => depth == 0
+        };
+        if ended {
+            // a datum that's been commented out doesn't count
+            if comments > 0 {
+                comments -= 1;
+            } else {
+                return true;
+            }
+        }
+    }
+    false
+}
+
 #[derive(PartialEq, Clone, Debug)]
 pub enum Node {
     Identifier(String),
     Integer(i64),
+    Rational(i64, i64),
+    Float(f64),
+    Complex(Number, Number),
     Boolean(bool),
+    Char(char),
     String(String),
     List(Vec<Node>),
+    Vector(Vec<Node>),
+    // vectors have identity, so a labelled one gets an id, and its references point back at it by that id
+    LabelledVector(usize, Vec<Node>),
+    VectorReference(usize),
+    Bytevector(Vec<u8>),
 }
 
 pub struct ParseError {
@@ -39,11 +88,15 @@ macro_rules! parse_error {
 
 struct Parser<'a> {
     tokens: slice::Iter<'a, Token>,
+    // datum labels seen so far, None while the labelled datum is still being parsed
+    labels: HashMap<u64, Option<Node>>,
+    // ids for labelled vectors, which stay unique even when a label is reused
+    vectors: usize,
 }
 
 impl<'a> Parser<'a> {
     fn parse(tokens: &Vec<Token>) -> Result<Vec<Node>, ParseError> {
-        let mut parser = Parser { tokens: tokens.iter() };
+        let mut parser = Parser { tokens: tokens.iter(), labels: HashMap::new(), vectors: 0 };
         parser.parse_nodes(0)
     }
 
@@ -69,6 +122,21 @@ impl<'a> Parser<'a> {
                         let inner = try!(self.parse_nodes(depth + 1));
                         Ok(Some(Node::List(inner)))
                     },
+                    Token::VectorOpen => {
+                        let inner = try!(self.parse_nodes(depth + 1));
+                        Ok(Some(Node::Vector(inner)))
+                    },
+                    Token::BytevectorOpen => {
+                        let inner = try!(self.parse_nodes(depth + 1));
+                        let mut bytes = Vec::new();
+                        for node in inner.iter() {
+                            match *node {
+                                Node::Integer(i) if i >= 0 && i <= 255 => bytes.push(i as u8),
+                                _ => parse_error!("Bytevector literals may only contain bytes: {:?}", node)
+                            }
+                        }
+                        Ok(Some(Node::Bytevector(bytes)))
+                    },
                     Token::CloseParen => {
                         if depth > 0 {
                             Ok(None)
@@ -103,15 +171,62 @@ impl<'a> Parser<'a> {
                             None => parse_error!("Missing unquoted value, depth: {}", depth)
                         }
                     }
+                    Token::DatumComment => {
+                        // skip the commented out datum, and return the one after it instead
+                        match try!(self.parse_node(depth)) {
+                            Some(_) => self.parse_node(depth),
+                            None => parse_error!("Missing commented out datum, depth: {}", depth)
+                        }
+                    },
+                    Token::DatumLabel(label) => {
+                        if self.tokens.clone().next() == Some(&Token::VectorOpen) {
+                            // references to a vector can be resolved before it's finished, which allows cycles
+                            let id = self.vectors;
+                            self.vectors += 1;
+                            self.labels.insert(label, Some(Node::VectorReference(id)));
+                            return match try!(self.parse_node(depth)) {
+                                Some(Node::Vector(inner)) => Ok(Some(Node::LabelledVector(id, inner))),
+                                _ => parse_error!("Missing labelled datum for #{}=, depth: {}", label, depth)
+                            };
+                        }
+                        self.labels.insert(label, None);
+                        match try!(self.parse_node(depth)) {
+                            Some(node) => {
+                                self.labels.insert(label, Some(node.clone()));
+                                Ok(Some(node))
+                            },
+                            None => parse_error!("Missing labelled datum for #{}=, depth: {}", label, depth)
+                        }
+                    },
+                    Token::DatumReference(label) => {
+                        // lists are values rather than shared pairs, so references to them become copies
+                        match self.labels.get(&label) {
+                            Some(&Some(ref node)) => Ok(Some(node.clone())),
+                            Some(&None) => parse_error!("Cyclic datum label can't be represented: #{}#", label),
+                            None => parse_error!("Undefined datum label: #{}#", label)
+                        }
+                    },
                     Token::Identifier(ref val) => {
                         Ok(Some(Node::Identifier(val.clone())))
                     },
                     Token::Integer(ref val) => {
                         Ok(Some(Node::Integer(val.clone())))
                     },
+                    Token::Rational(n, d) => {
+                        Ok(Some(Node::Rational(n, d)))
+                    },
+                    Token::Float(ref val) => {
+                        Ok(Some(Node::Float(val.clone())))
+                    },
+                    Token::Complex(re, im) => {
+                        Ok(Some(Node::Complex(re, im)))
+                    },
                     Token::Boolean(ref val) => {
                         Ok(Some(Node::Boolean(val.clone())))
                     },
+                    Token::Char(val) => {
+                        Ok(Some(Node::Char(val)))
+                    },
                     Token::String(ref val) => {
                         Ok(Some(Node::String(val.clone())))
                     }
@@ -167,3 +282,48 @@ fn test_parser_bad_syntax() {
     assert_eq!(parse(&vec![Token::OpenParen, Token::OpenParen, Token::CloseParen, Token::OpenParen, Token::OpenParen, Token::CloseParen]).err().unwrap().to_string(),
                "ParseError: Unexpected end of input, depth: 2");
 }
+
+#[test]
+fn test_parser_datum_comments() {
+    assert_eq!(parse(&vec![Token::OpenParen, Token::Identifier("a".to_string()), Token::DatumComment, Token::OpenParen, Token::Identifier("b".to_string()), Token::CloseParen, Token::Identifier("c".to_string()), Token::CloseParen]).unwrap(),
+               vec![Node::List(vec![Node::Identifier("a".to_string()), Node::Identifier("c".to_string())])]);
+    assert_eq!(parse(&vec![Token::Integer(1), Token::DatumComment, Token::Integer(2)]).unwrap(),
+               vec![Node::Integer(1)]);
+    assert_eq!(parse(&vec![Token::OpenParen, Token::DatumComment, Token::CloseParen]).err().unwrap().to_string(),
+               "ParseError: Missing commented out datum, depth: 1");
+}
+
+#[test]
+fn test_parser_datum_labels() {
+    assert_eq!(parse(&vec![Token::OpenParen, Token::DatumLabel(0), Token::Identifier("a".to_string()), Token::DatumReference(0), Token::CloseParen]).unwrap(),
+               vec![Node::List(vec![Node::Identifier("a".to_string()), Node::Identifier("a".to_string())])]);
+    assert_eq!(parse(&vec![Token::DatumLabel(0), Token::OpenParen, Token::DatumReference(0), Token::CloseParen]).err().unwrap().to_string(),
+               "ParseError: Cyclic datum label can't be represented: #0#");
+    assert_eq!(parse(&vec![Token::DatumReference(3)]).err().unwrap().to_string(),
+               "ParseError: Undefined datum label: #3#");
+}
+
+#[test]
+fn test_parser_datum_complete() {
+    assert!(!datum_complete(&vec![]));
+    assert!(!datum_complete(&vec![Token::Quote, Token::OpenParen, Token::Integer(1)]));
+    assert!(datum_complete(&vec![Token::Quote, Token::OpenParen, Token::Integer(1), Token::CloseParen]));
+    assert!(!datum_complete(&vec![Token::DatumComment, Token::Integer(1)]));
+    assert!(datum_complete(&vec![Token::DatumComment, Token::Integer(1), Token::Identifier("a".to_string())]));
+}
+
+#[test]
+fn test_parser_vectors() {
+    assert_eq!(parse(&vec![Token::VectorOpen, Token::Integer(1), Token::VectorOpen, Token::CloseParen, Token::CloseParen]).unwrap(),
+               vec![Node::Vector(vec![Node::Integer(1), Node::Vector(vec![])])]);
+    assert_eq!(parse(&vec![Token::DatumLabel(0), Token::VectorOpen, Token::DatumReference(0), Token::Integer(2), Token::CloseParen, Token::DatumReference(0)]).unwrap(),
+               vec![Node::LabelledVector(0, vec![Node::VectorReference(0), Node::Integer(2)]), Node::VectorReference(0)]);
+}
+
+#[test]
+fn test_parser_bytevectors() {
+    assert_eq!(parse(&vec![Token::BytevectorOpen, Token::Integer(0), Token::Integer(255), Token::CloseParen]).unwrap(),
+               vec![Node::Bytevector(vec![0, 255])]);
+    assert_eq!(parse(&vec![Token::BytevectorOpen, Token::Integer(256), Token::CloseParen]).err().unwrap().to_string(),
+               "ParseError: Bytevector literals may only contain bytes: Integer(256)");
+}